@@ -0,0 +1,536 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError, StdResult, Storage, Uint128, WasmMsg, to_json_binary};
+use cw2::set_contract_version;
+use cw20::{AllAccountsResponse, AllAllowancesResponse, AllowanceInfo, AllowanceResponse, BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, Expiration, MarketingInfoResponse, MinterResponse, TokenInfoResponse};
+use cw_storage_plus::Bound;
+
+use crate::error::ContractError;
+use crate::msg::{InstantiateMsg, MinterHookMsg};
+use crate::state::{TokenInfo, ALLOWANCES, BALANCES, TOKEN_INFO};
+
+//Receipts stand 1:1 for stake in the minter. The minter mints them on stake and burns them on
+//unstake, every other move is reported to it so the stake behind the receipts moves along.
+
+const CONTRACT_NAME: &str = "crates.io:och-receipt-token";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+
+//_________________________________________________________________________
+
+//_______________________________
+//|                              |
+//|                              |
+//|         INSTANTIATE          |
+//|                              |
+//|______________________________|
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    TOKEN_INFO.save(deps.storage, &TokenInfo {
+        name: msg.name,
+        symbol: msg.symbol,
+        decimals: msg.decimals,
+        total_supply: Uint128::zero(),
+        minter: info.sender.clone(),
+    })?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attribute("minter", info.sender)
+    )
+}
+
+
+//_________________________________________________________________________
+
+//_______________________________
+//|                              |
+//|                              |
+//|          EXECUTE             |
+//|                              |
+//|______________________________|
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: Cw20ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        Cw20ExecuteMsg::Transfer { recipient, amount } => transfer(deps, env, info, None, recipient, amount),
+        Cw20ExecuteMsg::Send { contract, amount, msg } => send(deps, env, info, None, contract, amount, msg),
+        Cw20ExecuteMsg::TransferFrom { owner, recipient, amount } => transfer(deps, env, info, Some(owner), recipient, amount),
+        Cw20ExecuteMsg::SendFrom { owner, contract, amount, msg } => send(deps, env, info, Some(owner), contract, amount, msg),
+        Cw20ExecuteMsg::IncreaseAllowance { spender, amount, expires } => update_allowance(deps, env, info, spender, amount, expires, true),
+        Cw20ExecuteMsg::DecreaseAllowance { spender, amount, expires } => update_allowance(deps, env, info, spender, amount, expires, false),
+        Cw20ExecuteMsg::Mint { recipient, amount } => mint(deps, info, recipient, amount),
+        Cw20ExecuteMsg::Burn { amount } => burn(deps, env, info, None, amount),
+        Cw20ExecuteMsg::BurnFrom { owner, amount } => burn(deps, env, info, Some(owner), amount),
+        //The minter is fixed and there is no marketing info to maintain
+        Cw20ExecuteMsg::UpdateMinter { .. } | Cw20ExecuteMsg::UpdateMarketing { .. } | Cw20ExecuteMsg::UploadLogo(_) => {
+            Err(ContractError::Unsupported{})
+        }
+    }
+}
+
+/// Transfers `amount` from `owner`, the sender when `None`, to `recipient` and reports it to
+/// the minter. Spending another owner's receipts takes from the allowance they gave the sender.
+fn transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: Option<String>,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let owner = match owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender.clone(),
+    };
+    let recipient = deps.api.addr_validate(&recipient)?;
+
+    //Receipts the minter holds are burnt, only a `Send` tells it what to do with them
+    let token_info = TOKEN_INFO.load(deps.storage)?;
+    if recipient == token_info.minter {
+        return Err(ContractError::TransferToMinter{});
+    }
+    if owner != info.sender {
+        spend_allowance(deps.storage, &env, &owner, &info.sender, amount)?;
+    }
+    move_balance(deps.storage, &owner, &recipient, amount)?;
+
+    let mut res = Response::new()
+        .add_message(minter_hook_msg(&token_info.minter, &owner, &recipient, amount)?)
+        .add_attribute("action", "transfer")
+        .add_attribute("from", owner.to_string())
+        .add_attribute("to", recipient)
+        .add_attribute("amount", amount);
+    if owner != info.sender {
+        res = res.add_attribute("by", info.sender);
+    }
+    Ok(res)
+}
+
+/// Transfers like `transfer` to `contract` and calls its `Receive` hook with `msg`. Receipts
+/// sent to the minter aren't reported, the minter handles them in its `Receive` hook.
+fn send(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: Option<String>,
+    contract: String,
+    amount: Uint128,
+    msg: Binary,
+) -> Result<Response, ContractError> {
+    let owner = match owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender.clone(),
+    };
+    let contract = deps.api.addr_validate(&contract)?;
+    if owner != info.sender {
+        spend_allowance(deps.storage, &env, &owner, &info.sender, amount)?;
+    }
+    move_balance(deps.storage, &owner, &contract, amount)?;
+
+    let token_info = TOKEN_INFO.load(deps.storage)?;
+    let mut res = Response::new();
+    if contract != token_info.minter {
+        res = res.add_message(minter_hook_msg(&token_info.minter, &owner, &contract, amount)?);
+    }
+    let receive = Cw20ReceiveMsg {
+        sender: owner.to_string(),
+        amount,
+        msg,
+    };
+
+    res = res
+        .add_message(receive.into_cosmos_msg(contract.to_string())?)
+        .add_attribute("action", "send")
+        .add_attribute("from", owner.to_string())
+        .add_attribute("to", contract)
+        .add_attribute("amount", amount);
+    if owner != info.sender {
+        res = res.add_attribute("by", info.sender);
+    }
+    Ok(res)
+}
+
+fn mint(
+    deps: DepsMut,
+    info: MessageInfo,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let mut token_info = TOKEN_INFO.load(deps.storage)?;
+    if info.sender != token_info.minter {
+        return Err(ContractError::Unauthorized{});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount{});
+    }
+
+    let recipient = deps.api.addr_validate(&recipient)?;
+    BALANCES.update(deps.storage, recipient.clone(), |balance| -> StdResult<_> { Ok(balance.unwrap_or_default().checked_add(amount)?) })?;
+    token_info.total_supply = token_info.total_supply.checked_add(amount)?;
+    TOKEN_INFO.save(deps.storage, &token_info)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "mint")
+        .add_attribute("to", recipient)
+        .add_attribute("amount", amount)
+    )
+}
+
+/// Burns `amount` of `owner`, the minter itself when `None`. Only the minter burns, out of
+/// the allowance `owner` gave it, since the stake behind the receipts leaves with them.
+fn burn(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: Option<String>,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let mut token_info = TOKEN_INFO.load(deps.storage)?;
+    if info.sender != token_info.minter {
+        return Err(ContractError::Unauthorized{});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount{});
+    }
+
+    let owner = match owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender.clone(),
+    };
+    if owner != info.sender {
+        spend_allowance(deps.storage, &env, &owner, &info.sender, amount)?;
+    }
+    BALANCES.update(deps.storage, owner.clone(), |balance| -> StdResult<_> { Ok(balance.unwrap_or_default().checked_sub(amount)?) })?;
+    token_info.total_supply = token_info.total_supply.checked_sub(amount)?;
+    TOKEN_INFO.save(deps.storage, &token_info)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "burn")
+        .add_attribute("from", owner)
+        .add_attribute("amount", amount)
+    )
+}
+
+fn update_allowance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    spender: String,
+    amount: Uint128,
+    expires: Option<Expiration>,
+    increase: bool,
+) -> Result<Response, ContractError> {
+    let spender = deps.api.addr_validate(&spender)?;
+    if spender == info.sender {
+        return Err(ContractError::CannotSetOwnAllowance{});
+    }
+
+    let key = (info.sender.clone(), spender.clone());
+    let mut allowance = ALLOWANCES.may_load(deps.storage, key.clone())?.unwrap_or_default();
+    if let Some(expires) = expires {
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::AllowanceExpired{});
+        }
+        allowance.expires = expires;
+    }
+    allowance.allowance = match increase {
+        true => allowance.allowance.checked_add(amount)?,
+        false => allowance.allowance.saturating_sub(amount),
+    };
+
+    //Nothing left to spend, drop the entry
+    if allowance.allowance.is_zero() {
+        ALLOWANCES.remove(deps.storage, key);
+    } else {
+        ALLOWANCES.save(deps.storage, key, &allowance)?;
+    }
+
+    Ok(
+        Response::new()
+        .add_attribute("action", if increase { "increase_allowance" } else { "decrease_allowance" })
+        .add_attribute("owner", info.sender)
+        .add_attribute("spender", spender)
+        .add_attribute("amount", amount)
+    )
+}
+
+/// Takes `amount` out of the allowance `owner` gave `spender`
+fn spend_allowance(
+    storage: &mut dyn Storage,
+    env: &Env,
+    owner: &Addr,
+    spender: &Addr,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let key = (owner.clone(), spender.clone());
+    let mut allowance = ALLOWANCES.may_load(storage, key.clone())?.unwrap_or_default();
+    if allowance.expires.is_expired(&env.block) {
+        return Err(ContractError::AllowanceExpired{});
+    }
+    allowance.allowance = allowance.allowance
+        .checked_sub(amount)
+        .map_err(|_| ContractError::NotEnoughAllowance{})?;
+    ALLOWANCES.save(storage, key, &allowance)?;
+    Ok(())
+}
+
+fn move_balance(
+    storage: &mut dyn Storage,
+    owner: &Addr,
+    recipient: &Addr,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount{});
+    }
+
+    BALANCES.update(storage, owner.clone(), |balance| -> StdResult<_> { Ok(balance.unwrap_or_default().checked_sub(amount)?) })?;
+    BALANCES.update(storage, recipient.clone(), |balance| -> StdResult<_> { Ok(balance.unwrap_or_default().checked_add(amount)?) })?;
+    Ok(())
+}
+
+/// Tells the minter `amount` moved from `owner` to `recipient`, failing the move if it refuses
+fn minter_hook_msg(minter: &Addr, owner: &Addr, recipient: &Addr, amount: Uint128) -> StdResult<WasmMsg> {
+    Ok(WasmMsg::Execute {
+        contract_addr: minter.to_string(),
+        msg: to_json_binary(&MinterHookMsg::ReceiptTransfer {
+            owner: owner.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+        })?,
+        funds: vec![],
+    })
+}
+
+
+//_________________________________________________________________________
+
+//_______________________________
+//|                              |
+//|                              |
+//|           QUERY              |
+//|                              |
+//|______________________________|
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(
+    deps: Deps,
+    _env: Env,
+    msg: Cw20QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        Cw20QueryMsg::Balance { address } => query_balance(deps, address),
+        Cw20QueryMsg::TokenInfo {  } => query_token_info(deps),
+        Cw20QueryMsg::Minter {  } => query_minter(deps),
+        Cw20QueryMsg::Allowance { owner, spender } => query_allowance(deps, owner, spender),
+        Cw20QueryMsg::AllAllowances { owner, start_after, limit } => query_all_allowances(deps, owner, start_after, limit),
+        Cw20QueryMsg::AllAccounts { start_after, limit } => query_all_accounts(deps, start_after, limit),
+        Cw20QueryMsg::MarketingInfo {  } => to_json_binary(&MarketingInfoResponse::default()),
+        Cw20QueryMsg::DownloadLogo {  } => Err(StdError::not_found("logo")),
+    }
+}
+
+fn query_balance(
+    deps: Deps,
+    address: String,
+) -> StdResult<Binary> {
+    let address = deps.api.addr_validate(&address)?;
+    let balance = BALANCES.may_load(deps.storage, address)?.unwrap_or_default();
+    to_json_binary(&BalanceResponse { balance })
+}
+
+fn query_token_info(
+    deps: Deps,
+) -> StdResult<Binary> {
+    let token_info = TOKEN_INFO.load(deps.storage)?;
+    to_json_binary(&TokenInfoResponse {
+        name: token_info.name,
+        symbol: token_info.symbol,
+        decimals: token_info.decimals,
+        total_supply: token_info.total_supply,
+    })
+}
+
+fn query_minter(
+    deps: Deps,
+) -> StdResult<Binary> {
+    let token_info = TOKEN_INFO.load(deps.storage)?;
+    to_json_binary(&MinterResponse {
+        minter: token_info.minter.to_string(),
+        cap: None,
+    })
+}
+
+fn query_allowance(
+    deps: Deps,
+    owner: String,
+    spender: String,
+) -> StdResult<Binary> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let spender = deps.api.addr_validate(&spender)?;
+    let allowance: AllowanceResponse = ALLOWANCES.may_load(deps.storage, (owner, spender))?.unwrap_or_default();
+    to_json_binary(&allowance)
+}
+
+/// Allowances `owner` gave, in spender order
+fn query_all_allowances(
+    deps: Deps,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?.map(Bound::exclusive);
+
+    let allowances = ALLOWANCES
+        .prefix(owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (spender, allowance) = item?;
+            Ok(AllowanceInfo {
+                spender: spender.to_string(),
+                allowance: allowance.allowance,
+                expires: allowance.expires,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_json_binary(&AllAllowancesResponse { allowances })
+}
+
+/// Every address that ever held receipts, in address order
+fn query_all_accounts(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?.map(Bound::exclusive);
+
+    let accounts = BALANCES
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(String::from))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_json_binary(&AllAccountsResponse { accounts })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{from_json, to_json_binary, CosmosMsg, OwnedDeps, Uint128, WasmMsg};
+    use cw20::{BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, TokenInfoResponse};
+
+    use crate::contract::{execute, instantiate, query};
+    use crate::error::ContractError;
+    use crate::msg::{InstantiateMsg, MinterHookMsg};
+
+    const MINTER: &str = "staking";
+    const OWNER: &str = "owner";
+    const SPENDER: &str = "spender";
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            name: "Staked ORAI".to_string(),
+            symbol: "SORAI".to_string(),
+            decimals: 6,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info(MINTER, &[]), msg).unwrap();
+        let mint = Cw20ExecuteMsg::Mint { recipient: OWNER.to_string(), amount: Uint128::new(1000) };
+        execute(deps.as_mut(), mock_env(), mock_info(MINTER, &[]), mint).unwrap();
+        deps
+    }
+
+    fn balance(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, address: &str) -> Uint128 {
+        let msg = Cw20QueryMsg::Balance { address: address.to_string() };
+        let res: BalanceResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        res.balance
+    }
+
+    fn hook(owner: &str, recipient: &str, amount: u128) -> CosmosMsg {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: MINTER.to_string(),
+            msg: to_json_binary(&MinterHookMsg::ReceiptTransfer {
+                owner: owner.to_string(),
+                recipient: recipient.to_string(),
+                amount: Uint128::new(amount),
+            }).unwrap(),
+            funds: vec![],
+        })
+    }
+
+    #[test]
+    fn only_the_minter_mints_and_burns() {
+        let mut deps = setup();
+
+        let mint = Cw20ExecuteMsg::Mint { recipient: OWNER.to_string(), amount: Uint128::new(1) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), mint).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized{}));
+        let burn = Cw20ExecuteMsg::Burn { amount: Uint128::new(1) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), burn).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized{}));
+
+        //BurnFrom takes from the allowance the owner gave the minter
+        let burn_from = Cw20ExecuteMsg::BurnFrom { owner: OWNER.to_string(), amount: Uint128::new(300) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info(MINTER, &[]), burn_from.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotEnoughAllowance{}));
+        let allow = Cw20ExecuteMsg::IncreaseAllowance { spender: MINTER.to_string(), amount: Uint128::new(300), expires: None };
+        execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), allow).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info(MINTER, &[]), burn_from.clone()).unwrap();
+        assert_eq!(balance(&deps, OWNER), Uint128::new(700));
+        let err = execute(deps.as_mut(), mock_env(), mock_info(MINTER, &[]), burn_from).unwrap_err();
+        assert!(matches!(err, ContractError::NotEnoughAllowance{}));
+
+        let info: TokenInfoResponse = from_json(query(deps.as_ref(), mock_env(), Cw20QueryMsg::TokenInfo {}).unwrap()).unwrap();
+        assert_eq!(info.total_supply, Uint128::new(700));
+    }
+
+    #[test]
+    fn moves_are_reported_to_the_minter() {
+        let mut deps = setup();
+
+        let transfer = Cw20ExecuteMsg::Transfer { recipient: SPENDER.to_string(), amount: Uint128::new(100) };
+        let res = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), transfer).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, hook(OWNER, SPENDER, 100));
+        assert_eq!(balance(&deps, SPENDER), Uint128::new(100));
+
+        //Receipts only reach the minter through Send, which it handles itself
+        let transfer = Cw20ExecuteMsg::Transfer { recipient: MINTER.to_string(), amount: Uint128::new(100) };
+        let err = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), transfer).unwrap_err();
+        assert!(matches!(err, ContractError::TransferToMinter{}));
+        let msg = to_json_binary("unstake").unwrap();
+        let send = Cw20ExecuteMsg::Send { contract: MINTER.to_string(), amount: Uint128::new(100), msg: msg.clone() };
+        let res = execute(deps.as_mut(), mock_env(), mock_info(OWNER, &[]), send).unwrap();
+        let receive = Cw20ReceiveMsg { sender: OWNER.to_string(), amount: Uint128::new(100), msg };
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, receive.into_cosmos_msg(MINTER).unwrap());
+        assert_eq!(balance(&deps, OWNER), Uint128::new(800));
+    }
+}
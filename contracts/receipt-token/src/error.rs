@@ -0,0 +1,32 @@
+use cosmwasm_std::{OverflowError, StdError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("Unauthorized")]
+    Unauthorized{},
+
+    #[error("Amount must be positive")]
+    InvalidZeroAmount{},
+
+    #[error("Not enough allowance")]
+    NotEnoughAllowance{},
+
+    #[error("Allowance is expired")]
+    AllowanceExpired{},
+
+    #[error("Cannot set an allowance for the own account")]
+    CannotSetOwnAllowance{},
+
+    #[error("Receipts only return to the minter through Send")]
+    TransferToMinter{},
+
+    #[error("Not supported by the receipt token")]
+    Unsupported{},
+}
@@ -0,0 +1,24 @@
+use cosmwasm_std::Uint128;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+//Execute and query messages are the cw20 ones, `cw20::Cw20ExecuteMsg` and `cw20::Cw20QueryMsg`
+
+/// The instantiating contract becomes the minter
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Sent to the minter whenever receipts change hands outside of minting and burning
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MinterHookMsg {
+    ReceiptTransfer {
+        owner: String,
+        recipient: String,
+        amount: Uint128,
+    },
+}
@@ -0,0 +1,20 @@
+use cosmwasm_std::{Addr, Uint128};
+use cw20::AllowanceResponse;
+use cw_storage_plus::{Item, Map};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenInfo {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: Uint128,
+    /// Staking contract minting and burning the receipts, told about every transfer
+    pub minter: Addr,
+}
+
+pub const TOKEN_INFO: Item<TokenInfo> = Item::new("token_info");
+pub const BALANCES: Map<Addr, Uint128> = Map::new("balances");
+/// Receipts spenders may move for their owner, keyed by (owner, spender)
+pub const ALLOWANCES: Map<(Addr, Addr), AllowanceResponse> = Map::new("allowances");
@@ -78,8 +78,13 @@ async function instantiate(codeID: number): Promise<InstantiateResult> {
     const msg = {
         admin: sender,
         updater: sender,
-        rps: "1000",
-        oracle: "2920000",
+        stake_denom: { native: "orai" },
+        rewards: [{
+            denom: { cw20: "orai14x647uadcp3wxav6vvjyq23vtwvkkhqnfy9w4vp77h36qx3gdmhq0ws7zh" },
+            schedules: [],
+            oracle: "2.92",
+        }],
+        unbonding_period: 604800,
     }
     const label = "test";
     const fee = "auto";
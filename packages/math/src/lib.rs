@@ -6,7 +6,10 @@ use std::ops;
 use std::str::FromStr;
 
 use bigint::U256;
-use cosmwasm_std::{Decimal, StdError, Uint128};
+use cosmwasm_std::{
+    CheckedFromRatioError, CheckedMultiplyRatioError, ConversionOverflowError, Decimal,
+    OverflowError, OverflowOperation, StdError, Uint128,
+};
 
 /// A fixed-point decimal value with 18 fractional digits, i.e. Decimal256(1_000_000_000_000_000_000) == 1.0
 /// The greatest possible value that can be represented is 115792089237316195423570985008687907853269984665640564039457.584007913129639935 (which is (2^128 - 1) / 10^18)
@@ -48,6 +51,39 @@ impl Decimal256 {
         Decimal256(nominator * Decimal256::DECIMAL_FRACTIONAL / denominator)
     }
 
+    /// Returns the ratio (nominator / denominator) as a Decimal256, or an error
+    /// instead of panicking on a zero denominator or an overflowing nominator
+    pub fn checked_from_ratio<A: Into<U256>, B: Into<U256>>(
+        nominator: A,
+        denominator: B,
+    ) -> Result<Decimal256, CheckedFromRatioError> {
+        let nominator: U256 = nominator.into();
+        let denominator: U256 = denominator.into();
+        if denominator.is_zero() {
+            return Err(CheckedFromRatioError::DivideByZero);
+        }
+
+        let (scaled, overflow) = nominator.overflowing_mul(Decimal256::DECIMAL_FRACTIONAL);
+        if overflow {
+            return Err(CheckedFromRatioError::Overflow);
+        }
+        Ok(Decimal256(scaled / denominator))
+    }
+
+    pub fn checked_add(self, other: Decimal256) -> Result<Decimal256, OverflowError> {
+        match self.0.overflowing_add(other.0) {
+            (value, false) => Ok(Decimal256(value)),
+            (_, true) => Err(OverflowError::new(OverflowOperation::Add, self, other)),
+        }
+    }
+
+    pub fn checked_sub(self, other: Decimal256) -> Result<Decimal256, OverflowError> {
+        match self.0.overflowing_sub(other.0) {
+            (value, false) => Ok(Decimal256(value)),
+            (_, true) => Err(OverflowError::new(OverflowOperation::Sub, self, other)),
+        }
+    }
+
     pub fn from_uint256<A: Into<Uint256>>(val: A) -> Decimal256 {
         let num: Uint256 = val.into();
         Decimal256(num.0 * Decimal256::DECIMAL_FRACTIONAL)
@@ -509,6 +545,60 @@ impl Uint256 {
         let val = self.0 * nominator / denominator;
         Uint256::from(val)
     }
+
+    /// returns self * nom / denom, or an error instead of panicking
+    pub fn checked_multiply_ratio<A: Into<U256>, B: Into<U256>>(
+        &self,
+        nom: A,
+        denom: B,
+    ) -> Result<Uint256, CheckedMultiplyRatioError> {
+        let nominator: U256 = nom.into();
+        let denominator: U256 = denom.into();
+        if denominator.is_zero() {
+            return Err(CheckedMultiplyRatioError::DivideByZero);
+        }
+
+        let (product, overflow) = self.0.overflowing_mul(nominator);
+        if overflow {
+            return Err(CheckedMultiplyRatioError::Overflow);
+        }
+        Ok(Uint256(product / denominator))
+    }
+
+    pub fn checked_add(self, other: Uint256) -> Result<Uint256, OverflowError> {
+        match self.0.overflowing_add(other.0) {
+            (value, false) => Ok(Uint256(value)),
+            (_, true) => Err(OverflowError::new(OverflowOperation::Add, self, other)),
+        }
+    }
+
+    pub fn checked_sub(self, other: Uint256) -> Result<Uint256, OverflowError> {
+        match self.0.overflowing_sub(other.0) {
+            (value, false) => Ok(Uint256(value)),
+            (_, true) => Err(OverflowError::new(OverflowOperation::Sub, self, other)),
+        }
+    }
+
+    pub fn checked_mul(self, other: Uint256) -> Result<Uint256, OverflowError> {
+        match self.0.overflowing_mul(other.0) {
+            (value, false) => Ok(Uint256(value)),
+            (_, true) => Err(OverflowError::new(OverflowOperation::Mul, self, other)),
+        }
+    }
+
+    /// returns self * rhs rounded down, or an error instead of panicking
+    pub fn checked_mul_decimal(self, rhs: Decimal256) -> Result<Uint256, CheckedMultiplyRatioError> {
+        self.checked_multiply_ratio(rhs.0, Decimal256::DECIMAL_FRACTIONAL)
+    }
+
+    /// Narrows to a Uint128, or an error instead of panicking when the value does not fit
+    pub fn try_into_uint128(self) -> Result<Uint128, ConversionOverflowError> {
+        let U256(ref arr) = self.0;
+        if arr[2] != 0 || arr[3] != 0 {
+            return Err(ConversionOverflowError::new("Uint256", "Uint128", self.to_string()));
+        }
+        Ok(Uint128::from(((arr[1] as u128) << 64) + (arr[0] as u128)))
+    }
 }
 
 /// Serializes as a base64 string
@@ -554,7 +644,7 @@ impl<'de> de::Visitor<'de> for Uint256Visitor {
 #[cfg(test)]
 mod test {
     use super::*;
-    use cosmwasm_std::{from_slice, to_vec, StdResult};
+    use cosmwasm_std::{from_json, to_json_vec, StdResult};
     use std::convert::TryInto;
 
     #[test]
@@ -861,47 +951,47 @@ mod test {
 
     #[test]
     fn decimal_serialize() {
-        assert_eq!(to_vec(&Decimal256::zero()).unwrap(), br#""0""#);
-        assert_eq!(to_vec(&Decimal256::one()).unwrap(), br#""1""#);
-        assert_eq!(to_vec(&Decimal256::percent(8)).unwrap(), br#""0.08""#);
-        assert_eq!(to_vec(&Decimal256::percent(87)).unwrap(), br#""0.87""#);
-        assert_eq!(to_vec(&Decimal256::percent(876)).unwrap(), br#""8.76""#);
-        assert_eq!(to_vec(&Decimal256::percent(8765)).unwrap(), br#""87.65""#);
+        assert_eq!(to_json_vec(&Decimal256::zero()).unwrap(), br#""0""#);
+        assert_eq!(to_json_vec(&Decimal256::one()).unwrap(), br#""1""#);
+        assert_eq!(to_json_vec(&Decimal256::percent(8)).unwrap(), br#""0.08""#);
+        assert_eq!(to_json_vec(&Decimal256::percent(87)).unwrap(), br#""0.87""#);
+        assert_eq!(to_json_vec(&Decimal256::percent(876)).unwrap(), br#""8.76""#);
+        assert_eq!(to_json_vec(&Decimal256::percent(8765)).unwrap(), br#""87.65""#);
     }
 
     #[test]
     fn decimal_deserialize() {
         assert_eq!(
-            from_slice::<Decimal256>(br#""0""#).unwrap(),
+            from_json::<Decimal256>(br#""0""#).unwrap(),
             Decimal256::zero()
         );
         assert_eq!(
-            from_slice::<Decimal256>(br#""1""#).unwrap(),
+            from_json::<Decimal256>(br#""1""#).unwrap(),
             Decimal256::one()
         );
         assert_eq!(
-            from_slice::<Decimal256>(br#""000""#).unwrap(),
+            from_json::<Decimal256>(br#""000""#).unwrap(),
             Decimal256::zero()
         );
         assert_eq!(
-            from_slice::<Decimal256>(br#""001""#).unwrap(),
+            from_json::<Decimal256>(br#""001""#).unwrap(),
             Decimal256::one()
         );
 
         assert_eq!(
-            from_slice::<Decimal256>(br#""0.08""#).unwrap(),
+            from_json::<Decimal256>(br#""0.08""#).unwrap(),
             Decimal256::percent(8)
         );
         assert_eq!(
-            from_slice::<Decimal256>(br#""0.87""#).unwrap(),
+            from_json::<Decimal256>(br#""0.87""#).unwrap(),
             Decimal256::percent(87)
         );
         assert_eq!(
-            from_slice::<Decimal256>(br#""8.76""#).unwrap(),
+            from_json::<Decimal256>(br#""8.76""#).unwrap(),
             Decimal256::percent(876)
         );
         assert_eq!(
-            from_slice::<Decimal256>(br#""87.65""#).unwrap(),
+            from_json::<Decimal256>(br#""87.65""#).unwrap(),
             Decimal256::percent(8765)
         );
     }
@@ -932,9 +1022,9 @@ mod test {
     #[test]
     fn uint256_json() {
         let orig = Uint256::from(1234567890987654321u64);
-        let serialized = to_vec(&orig).unwrap();
+        let serialized = to_json_vec(&orig).unwrap();
         assert_eq!(serialized.as_slice(), b"\"1234567890987654321\"");
-        let parsed: Uint256 = from_slice(&serialized).unwrap();
+        let parsed: Uint256 = from_json(&serialized).unwrap();
         assert_eq!(parsed, orig);
     }
 
@@ -1035,6 +1125,52 @@ mod test {
         Uint256::from(500u64).multiply_ratio(1, 0);
     }
 
+    #[test]
+    fn checked_ops_return_errors_instead_of_panicking() {
+        assert_eq!(
+            Decimal256::checked_from_ratio(1u64, 3u64).unwrap(),
+            Decimal256::from_ratio(1u64, 3u64)
+        );
+        assert_eq!(
+            Decimal256::checked_from_ratio(1u64, 0u64),
+            Err(CheckedFromRatioError::DivideByZero)
+        );
+        assert_eq!(
+            Decimal256::checked_from_ratio(U256::MAX, 1u64),
+            Err(CheckedFromRatioError::Overflow)
+        );
+        assert!(Decimal256::MAX.checked_add(Decimal256::one()).is_err());
+        assert!(Decimal256::zero().checked_sub(Decimal256::one()).is_err());
+
+        let max = Uint256(U256::MAX);
+        assert_eq!(
+            Uint256::from(500u64).checked_multiply_ratio(1u64, 2u64).unwrap(),
+            Uint256::from(250u64)
+        );
+        assert_eq!(
+            Uint256::from(500u64).checked_multiply_ratio(1u64, 0u64),
+            Err(CheckedMultiplyRatioError::DivideByZero)
+        );
+        assert_eq!(
+            max.checked_multiply_ratio(2u64, 2u64),
+            Err(CheckedMultiplyRatioError::Overflow)
+        );
+        assert!(max.checked_add(Uint256::one()).is_err());
+        assert!(Uint256::zero().checked_sub(Uint256::one()).is_err());
+        assert!(max.checked_mul(Uint256::from(2u64)).is_err());
+        assert_eq!(
+            Uint256::from(300u64).checked_mul_decimal(Decimal256::percent(150)).unwrap(),
+            Uint256::from(450u64)
+        );
+        assert!(max.checked_mul_decimal(Decimal256::percent(150)).is_err());
+
+        assert_eq!(
+            Uint256::from(u128::MAX).try_into_uint128().unwrap(),
+            Uint128::MAX
+        );
+        assert!(max.try_into_uint128().is_err());
+    }
+
     #[test]
     fn u256_zero_one() {
         assert_eq!(Uint256::zero().0, U256::zero());
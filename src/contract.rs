@@ -1,24 +1,37 @@
-
 #[cfg(not(feature = "library"))]
-
 use cosmwasm_std::entry_point;
-use cosmwasm_std::{Addr, Coin, Empty, StdAck, Uint128};
-use cosmwasm_std::{Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, to_json_binary, BankMsg, CosmosMsg, WasmMsg, WasmQuery, QueryRequest};
+use cosmwasm_std::{Addr, Coin, Decimal, Order, Storage, Timestamp, Uint128};
+use cw_storage_plus::{Bound, Item};
+use cw_utils::parse_reply_instantiate_data;
+use std::cmp::Reverse;
+use cosmwasm_std::{Binary, Deps, DepsMut, DistributionMsg, Env, MessageInfo, QuerierWrapper, QueryRequest, Reply, Response, StakingMsg, SubMsg, StdError, StdResult, from_json, to_json_binary, BankMsg, CosmosMsg, WasmMsg, WasmQuery};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ViewAPRResponse, ViewRewardResponse, ViewStakeAmount, ViewBalance};
+use crate::migrations;
+use crate::oracle::{load_twap, save_price};
+use crate::reward::{accrued_rewards, apr, apy, current_rps, distribute, load_rewards, settle_user_rewards, update_rewards};
+use crate::msg::{AllStakersResponse, ConfigResponse, EarlyExitPenaltyMsg, ExecuteMsg, HooksResponse, InfoResponse, InstantiateMsg, MigrateMsg, OperatorResponse, OperatorsResponse, OraclePriceResponse, OracleQueryMsg, PauseStateResponse, PendingUnbondingsResponse, QueryMsg, ReceiptTokenInstantiateMsg, ReceiveMsg, RewardAmount, RewardReserve, RewardReserveResponse, RewardSchedulesResponse, StakeChangedHookMsg, StakerResponse, StakerReward, TotalPowerAtHeightResponse, TwapResponse, UserLocksResponse, ViewAPRResponse, ViewRewardResponse, ViewStakeAmount, VotingPowerAtHeightResponse};
 
-use cw2::set_contract_version;
-use cw20::{self, Cw20ExecuteMsg};
+use cw2::{get_contract_version, set_contract_version};
+use semver::Version;
+use cw20::{Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, Denom, Expiration, TokenInfoResponse, UncheckedDenom};
 
-use crate::state::{Config, StakeInfo, RewardInfo, CONFIG, USERS, REWARD, ORACLE};
+use crate::state::{asset_key, Config, DelegationConfig, EarlyExitPenalty, LockPosition, LockTier, OperatorGrant, PauseInfo, PendingRole, PriceInfo, RewardInfo, RewardSchedule, StakeInfo, UnbondingInfo, UndelegationBatch, CONFIG, HOOKS, USERS, OPERATORS, REWARDS, ORACLE, STAKED_BALANCES, TOTAL_STAKE, TOTAL_WEIGHT, UNBONDINGS, LAST_REDELEGATION, SENT_BATCHES, UNDELEGATION_BATCH, PENDING_ADMIN, PENDING_PENALTIES, PENDING_UPDATER, PAUSE, TREASURY_PENALTIES};
 
 
 const CONTRACT_NAME: &str = "crates.io:och-staking";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const year: Uint128 = Uint128::new(31536000);
-const PRECISION: Uint128 = Uint128::new(10u128.pow(6));
-const USDC_ADDRESS: &str = "orai14x647uadcp3wxav6vvjyq23vtwvkkhqnfy9w4vp77h36qx3gdmhq0ws7zh";
+
+const NATIVE_DECIMALS: u8 = 6;
+pub(crate) const DEFAULT_MAX_PRICE_AGE: u64 = 86400;
+const MAX_COMPOUND_BOUNTY_BPS: u16 = 1000;
+const DEFAULT_MAX_ENTRIES: u32 = 7;
+
+const INSTANTIATE_RECEIPT_TOKEN_REPLY_ID: u64 = 1;
+
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
 
 
 //_________________________________________________________________________
@@ -32,7 +45,7 @@ const USDC_ADDRESS: &str = "orai14x647uadcp3wxav6vvjyq23vtwvkkhqnfy9w4vp77h36qx3
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
@@ -46,37 +59,124 @@ pub fn instantiate(
     let validated_admin  = deps.api.addr_validate(&admin)?;
     let validated_updater = deps.api.addr_validate(&updater)?;
 
+    let stake_denom = msg.stake_denom.into_checked(deps.as_ref())?;
+    let guardian = msg.guardian.map(|guardian| deps.api.addr_validate(&guardian)).transpose()?;
+    let lock_tiers = msg.lock_tiers.unwrap_or_default();
+    validate_lock_tiers(&lock_tiers)?;
+    let early_exit_penalty = msg.early_exit_penalty.map(|penalty| validate_early_exit_penalty(deps.as_ref(), penalty)).transpose()?;
+    if msg.receipt_token.is_some() && !lock_tiers.is_empty() {
+        return Err(ContractError::ReceiptTokenWithLocks{});
+    }
+    let compound_bounty_bps = msg.compound_bounty_bps.unwrap_or(0);
+    validate_compound_bounty(compound_bounty_bps)?;
+    let price_oracle = msg.price_oracle.map(|price_oracle| deps.api.addr_validate(&price_oracle)).transpose()?;
+    let price_feeder = match msg.price_feeder {
+        Some(price_feeder) => deps.api.addr_validate(&price_feeder)?,
+        None => validated_admin.clone(),
+    };
     let config = Config {
         admin: validated_admin.clone(),
         updater: validated_updater.clone(),
+        stake_denom,
+        unbonding_period: msg.unbonding_period.unwrap_or(0),
+        guardian,
+        price_feeder,
+        max_price_age: msg.max_price_age.unwrap_or(DEFAULT_MAX_PRICE_AGE),
+        max_price_deviation: msg.max_price_deviation,
+        price_oracle,
+        lock_tiers,
+        early_exit_penalty,
+        compound_bounty_bps,
+        delegation: None,
+        receipt_token: None,
     };
 
     CONFIG.save(deps.storage, &config)?;
+    PAUSE.save(deps.storage, &PauseInfo::default())?;
+    TOTAL_STAKE.save(deps.storage, &Uint128::zero(), env.block.height)?;
+    TOTAL_WEIGHT.save(deps.storage, &Uint128::zero())?;
+
+    // Init Rewards && oracle
+    for reward in msg.rewards {
+        let asset = add_reward_asset(deps.branch(), &env, reward.denom)?;
+
+        for schedule in reward.schedules {
+            let mut reward_info = REWARDS.load(deps.storage, &asset)?;
+            queue_schedule(&mut reward_info, schedule, env.block.time)?;
+            REWARDS.save(deps.storage, &asset, &reward_info)?;
+        }
 
-    // Init Reward
-    let rps_init = Uint128::new(msg.rps.unwrap_or(0));
-    
-    let reward = RewardInfo {
-        last_update: env.block.time,
-        cur_sum_index: Uint128::new(0),
-        rps: rps_init,
-        total_stake: Uint128::new(0),
-    };
-
-    REWARD.save(deps.storage, &reward)?;
+        if let Some(price) = reward.oracle {
+            save_price(deps.storage, &asset, price, env.block.time)?;
+        }
+    }
 
-    //Init oracle
-    let oracle_init = Uint128::new(msg.oracle.unwrap_or(0));
-    ORACLE.save(deps.storage, &oracle_init)?;
+    //The receipt token is recorded once it exists, see `reply`
+    let mut res = Response::new();
+    if let Some(receipt_token) = msg.receipt_token {
+        let instantiate_msg = ReceiptTokenInstantiateMsg {
+            name: receipt_token.name,
+            symbol: receipt_token.symbol.clone(),
+            decimals: token_decimals(deps.as_ref(), &config.stake_denom)?,
+        };
+        res = res.add_submessage(SubMsg::reply_on_success(
+            WasmMsg::Instantiate {
+                admin: Some(env.contract.address.to_string()),
+                code_id: receipt_token.code_id,
+                msg: to_json_binary(&instantiate_msg)?,
+                funds: vec![],
+                label: format!("{} receipt", receipt_token.symbol),
+            },
+            INSTANTIATE_RECEIPT_TOKEN_REPLY_ID,
+        ));
+    }
 
     Ok(
-        Response::new()
+        res
         .add_attribute("action", "instantiate")
         .add_attribute("admin", validated_admin.to_string())
         .add_attribute("updater", validated_updater.to_string())
     )
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        INSTANTIATE_RECEIPT_TOKEN_REPLY_ID => {
+            let res = parse_reply_instantiate_data(msg).map_err(|err| StdError::generic_err(err.to_string()))?;
+            let receipt_token = deps.api.addr_validate(&res.contract_address)?;
+
+            let mut config = CONFIG.load(deps.storage)?;
+            config.receipt_token = Some(receipt_token.clone());
+            CONFIG.save(deps.storage, &config)?;
+
+            Ok(
+                Response::new()
+                .add_attribute("action", "register_receipt_token")
+                .add_attribute("receipt_token", receipt_token)
+            )
+        }
+        id => Err(StdError::generic_err(format!("Unknown reply id {}", id)).into()),
+    }
+}
+
+/// Registers a new reward stream starting from a zero index, returns its asset key
+fn add_reward_asset(
+    deps: DepsMut,
+    env: &Env,
+    denom: UncheckedDenom,
+) -> Result<String, ContractError> {
+    let denom = denom.into_checked(deps.as_ref())?;
+    let asset = asset_key(&denom);
+
+    if REWARDS.has(deps.storage, &asset) {
+        return Err(ContractError::DuplicateRewardAsset{ asset });
+    }
+
+    REWARDS.save(deps.storage, &asset, &RewardInfo::new(denom, env.block.time))?;
+    Ok(asset)
+}
+
 
 //_________________________________________________________________________
 
@@ -95,516 +195,3465 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Stake {  } => stake(deps, env, info),
-        ExecuteMsg::Unstake { amount } => unstake(deps, env, info, amount),
+        ExecuteMsg::Stake {  } => stake(deps, env, info, None, None),
+        ExecuteMsg::StakeLocked { duration } => stake(deps, env, info, None, Some(duration)),
+        ExecuteMsg::StakeFor { recipient } => stake(deps, env, info, Some(recipient), None),
+        ExecuteMsg::Unlock { address } => unlock(deps, env, address),
+        ExecuteMsg::UpdateLockTiers { lock_tiers } => update_lock_tiers(deps, info, lock_tiers),
+        ExecuteMsg::UpdateEarlyExitPenalty { early_exit_penalty } => update_early_exit_penalty(deps, info, early_exit_penalty),
+        ExecuteMsg::Compound {  } => compound(deps, env, info),
+        ExecuteMsg::SetAutoCompound { enabled } => set_auto_compound(deps, info, enabled),
+        ExecuteMsg::CompoundFor { addresses } => compound_for(deps, env, info, addresses),
+        ExecuteMsg::UpdateCompoundBounty { bps } => update_compound_bounty(deps, info, bps),
+        ExecuteMsg::UpdateDelegation { validators, unbonding_period, max_entries } => update_delegation(deps, env, info, validators, unbonding_period, max_entries),
+        ExecuteMsg::Harvest {  } => harvest(deps, env),
+        ExecuteMsg::ReceiptTransfer { owner, recipient, amount } => receipt_transfer(deps, env, info, owner, recipient, amount),
+        ExecuteMsg::AddHook { addr } => add_hook(deps, info, addr),
+        ExecuteMsg::RemoveHook { addr } => remove_hook(deps, info, addr),
+        ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
+        ExecuteMsg::Unstake { amount, early_exit } => unstake(deps, env, info.sender, Uint128::from(amount), early_exit.unwrap_or(false), false),
+        ExecuteMsg::WithdrawUnbonded {  } => withdraw_unbonded(deps, env, info),
         ExecuteMsg::UpdateCurSumIndex {  } => update_cur_sum_index(deps, env),
-        ExecuteMsg::ClaimReward {  } => claim_reward(deps, env, info),
-        ExecuteMsg::UpdateOracle { price } => update_oracle(deps, env, info, price),
+        ExecuteMsg::ClaimReward { recipient } => {
+            let recipient = match recipient {
+                Some(recipient) => deps.api.addr_validate(&recipient)?,
+                None => info.sender.clone(),
+            };
+            claim_reward(deps, env, info.sender, recipient)
+        }
+        ExecuteMsg::AuthorizeOperator { operator, recipient } => authorize_operator(deps, info, operator, recipient),
+        ExecuteMsg::RevokeOperator { operator } => revoke_operator(deps, info, operator),
+        ExecuteMsg::ClaimRewardFor { owner } => claim_reward_for(deps, env, info, owner),
+        ExecuteMsg::FundRewards {  } => fund_native_rewards(deps, env, info),
+        ExecuteMsg::UpdateOracle { asset, price } => update_oracle(deps, env, info, asset, price),
         ExecuteMsg::UpdateUserReward { account } => update_user_reward(deps, env, info, account),
-        ExecuteMsg::UpdateRewardPerSecond { new_rps } => update_rps(deps, env, info, new_rps),
+        ExecuteMsg::AddRewardSchedule { asset, schedule } => add_reward_schedule(deps, env, info, asset, schedule),
+        ExecuteMsg::AddRewardAsset { denom } => execute_add_reward_asset(deps, env, info, denom),
+        ExecuteMsg::ProposeNewAdmin { address, expiry } => propose_role(deps, info, PENDING_ADMIN, "admin", address, expiry),
+        ExecuteMsg::AcceptAdmin {  } => accept_role(deps, env, info, PENDING_ADMIN, "admin", |config| &mut config.admin),
+        ExecuteMsg::CancelAdminProposal {  } => cancel_role_proposal(deps, info, PENDING_ADMIN, "admin"),
+        ExecuteMsg::ProposeNewUpdater { address, expiry } => propose_role(deps, info, PENDING_UPDATER, "updater", address, expiry),
+        ExecuteMsg::AcceptUpdater {  } => accept_role(deps, env, info, PENDING_UPDATER, "updater", |config| &mut config.updater),
+        ExecuteMsg::CancelUpdaterProposal {  } => cancel_role_proposal(deps, info, PENDING_UPDATER, "updater"),
+        ExecuteMsg::SetPause { stake, unstake, claim, oracle } => set_pause(deps, info, stake, unstake, claim, oracle),
+        ExecuteMsg::UpdateGuardian { guardian } => update_guardian(deps, info, guardian),
+        ExecuteMsg::UpdateOracleConfig { price_feeder, max_price_age, max_price_deviation, clear_max_price_deviation } => update_oracle_config(deps, info, price_feeder, max_price_age, max_price_deviation, clear_max_price_deviation.unwrap_or(false)),
+        ExecuteMsg::SetPriceOracle { price_oracle } => set_price_oracle(deps, info, price_oracle),
     }
 }
 
 
+/// Stakes the attached funds for `recipient`, the sender when `None`
 fn stake(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    recipient: Option<String>,
+    lock_duration: Option<u64>,
 ) -> Result<Response, ContractError> {
 
-    if info.funds.len() == 0 {
+    if info.funds.is_empty() {
         return Err(ContractError::WrongStakeAmount{});
     }
 
-    //Get user info
+    let config = CONFIG.load(deps.storage)?;
 
-    let account = info.sender;
+    let stake_denom = match config.stake_denom {
+        Denom::Native(denom) => denom,
+        Denom::Cw20(_) => return Err(ContractError::WrongNativeToken{}),
+    };
 
-    if info.funds.iter().any(|coin| coin.denom != "orai") {
+    if info.funds.iter().any(|coin| coin.denom != stake_denom) {
         return Err(ContractError::WrongNativeToken{});
     }
 
     let user_stake = Uint128::new(info.funds.iter().map(|coin| coin.amount.u128()).sum());
+    let account = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => info.sender.clone(),
+    };
     
+    execute_stake(deps, env, info.sender, account, user_stake, lock_duration)
+}
+
+fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let account = deps.api.addr_validate(&wrapper.sender)?;
+
+    let (recipient, lock_duration) = match from_json(&wrapper.msg)? {
+        ReceiveMsg::FundRewards {  } => return fund_rewards(deps, env, info.sender.as_str(), wrapper.amount),
+        ReceiveMsg::Unstake {  } => {
+            if config.receipt_token != Some(info.sender) {
+                return Err(ContractError::WrongCw20Token{});
+            }
+            return unstake(deps, env, account, wrapper.amount, false, true);
+        }
+        ReceiveMsg::Stake {  } => (account.clone(), None),
+        ReceiveMsg::StakeLocked { duration } => (account.clone(), Some(duration)),
+        ReceiveMsg::StakeFor { recipient } => (deps.api.addr_validate(&recipient)?, None),
+    };
+
+    //Only the configured staking token can be staked
+    if config.stake_denom != Denom::Cw20(info.sender) {
+        return Err(ContractError::WrongCw20Token{});
+    }
+    execute_stake(deps, env, account, recipient, wrapper.amount, lock_duration)
+}
+
+/// Credits `user_stake` paid by `funder` to `account` once the funds are known to be in the
+/// contract, locked for `lock_duration` when given. Only `account` can unstake it.
+fn execute_stake(
+    deps: DepsMut,
+    env: Env,
+    funder: Addr,
+    account: Addr,
+    user_stake: Uint128,
+    lock_duration: Option<u64>,
+) -> Result<Response, ContractError> {
+    ensure_not_paused(deps.storage, "stake", |pause| pause.stake)?;
+
     if user_stake == Uint128::zero() {
         return Err(ContractError::WrongStakeAmount{});
     }
-    
-    let user_stake_info = USERS.may_load(deps.storage, account.clone())?;
-    
-    //Update cur_sum_index
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed: u64 = cur.seconds() - reward_info.last_update.seconds();
 
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
-    }
+    //Pick the lock tier before touching any state
+    let lock = match lock_duration {
+        Some(duration) => {
+            let config = CONFIG.load(deps.storage)?;
+            let tier = config.lock_tiers
+                .into_iter()
+                .find(|tier| tier.duration == duration)
+                .ok_or(ContractError::UnknownLockTier{ duration })?;
+            Some(LockPosition {
+                amount: user_stake,
+                multiplier: tier.multiplier,
+                weight: user_stake.checked_mul_floor(tier.multiplier)?,
+                unlock_at: env.block.time.plus_seconds(duration),
+            })
+        }
+        None => None,
+    };
 
-    reward_info.last_update = cur;
+    //Pooled native stake goes on to the validators
+    let config = CONFIG.load(deps.storage)?;
+    let mut msgs = harvest_staking_rewards(deps.storage, &deps.querier, &env, &config)?.0;
+    msgs.extend(delegate_msgs(&config, user_stake));
+    msgs.extend(mint_receipt_msgs(&config, &account, user_stake)?);
+    
+    //Update cur_sum_index of every reward
+    let rewards = update_rewards(deps.storage, env.block.time)?;
 
     //Update User Stake
-
-    match user_stake_info {
-        Some(mut stake_info) => {
-            //Update user_reward
-            stake_info.reward = stake_info.reward + (reward_info.cur_sum_index - stake_info.index) * stake_info.balance / PRECISION;
-            stake_info.index = reward_info.cur_sum_index;
-            //Update user_balance
-            stake_info.balance = stake_info.balance + user_stake;
-
-            USERS.save(deps.storage, account, &stake_info)?;
-        },
-        None => {
-            let stake_info = StakeInfo {
-                balance: user_stake,
-                reward: Uint128::zero(),
-                index: reward_info.cur_sum_index,
-            };
-
-            USERS.save(deps.storage, account, &stake_info)?;
-        }
+    let mut stake_info = USERS.may_load(deps.storage, account.clone())?.unwrap_or_default();
+    settle_user_rewards(&mut stake_info, &rewards)?;
+    let weight_before = stake_info.weight();
+    release_expired_locks(&mut stake_info, env.block.time);
+    stake_info.balance += user_stake;
+
+    let mut res = Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "stake")
+        .add_attribute("account", account.to_string())
+        .add_attribute("amount", user_stake);
+    if funder != account {
+        res = res
+            .add_attribute("funder", funder)
+            .add_attribute("beneficiary", account.to_string());
+    }
+    if let Some(lock) = lock {
+        res = res
+            .add_attribute("unlock_at", lock.unlock_at.seconds().to_string())
+            .add_attribute("weight", lock.weight);
+        stake_info.locks.push(lock);
     }
 
-    reward_info.total_stake = reward_info.total_stake + user_stake;
-    REWARD.save(deps.storage, &reward_info)?;
+    let hooks = save_stake_info(deps.storage, &account, &stake_info, weight_before, env.block.height)?;
+    TOTAL_STAKE.update(deps.storage, env.block.height, |total| -> StdResult<_> { Ok(total.unwrap_or_default() + user_stake) })?;
 
-    Ok(Response::new())
+    Ok(res.add_submessages(hooks))
 }
 
+/// Unstakes `unstake_amount` of `account`. With a receipt token as many receipts are burnt,
+/// the ones `receipts_sent` along or else out of the allowance `account` gave this contract.
 fn unstake(
     deps: DepsMut,
     env: Env,
-    info: MessageInfo,
-    amount: u128,
+    account: Addr,
+    unstake_amount: Uint128,
+    early_exit: bool,
+    receipts_sent: bool,
 ) -> Result<Response, ContractError> {
 
+    ensure_not_paused(deps.storage, "unstake", |pause| pause.unstake)?;
+
+    if unstake_amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount{});
+    }
+
     //Get user_unstake_info 
-    let account = info.sender;
-    let address = account.clone().to_string();
-    let unstake_amount = Uint128::from(amount);
     let mut user_stake_info = USERS.load(deps.storage, account.clone())?;
 
+    //update rewards && user's reward
+    let config = CONFIG.load(deps.storage)?;
+    let mut msgs = harvest_staking_rewards(deps.storage, &deps.querier, &env, &config)?.0;
+    let rewards = update_rewards(deps.storage, env.block.time)?;
+    settle_user_rewards(&mut user_stake_info, &rewards)?;
+
+    let weight_before = user_stake_info.weight();
     if user_stake_info.balance < unstake_amount {
         return Err(ContractError::NotEnoughBalance{});
     }
+    if let Some(receipt_token) = &config.receipt_token {
+        let burn = match receipts_sent {
+            true => Cw20ExecuteMsg::Burn { amount: unstake_amount },
+            false => Cw20ExecuteMsg::BurnFrom { owner: account.to_string(), amount: unstake_amount },
+        };
+        msgs.push(receipt_msg(receipt_token, burn)?);
+    }
 
-    //get reward_info && update
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed = cur.seconds() - reward_info.last_update.seconds();
-
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
+    //Locked principal only becomes unstakable once its lock expires, or early at a penalty
+    release_expired_locks(&mut user_stake_info, env.block.time);
+    let unlocked = user_stake_info.balance - user_stake_info.locked();
+    let mut penalty = Uint128::zero();
+    if unlocked < unstake_amount {
+        let early_exit_penalty = match (&config.early_exit_penalty, early_exit) {
+            (Some(early_exit_penalty), true) => early_exit_penalty,
+            _ => return Err(ContractError::StakeLocked{}),
+        };
+        let broken = break_locks(&mut user_stake_info, unstake_amount - unlocked)?;
+        penalty = broken.multiply_ratio(early_exit_penalty.bps, 10_000u128);
     }
 
-    reward_info.last_update = cur;
+    //update user's balance, the unstaked amount stops earning from here on
+    user_stake_info.balance -= unstake_amount;
+    TOTAL_STAKE.update(deps.storage, env.block.height, |total| -> StdResult<_> { Ok(total.unwrap_or_default() - unstake_amount) })?;
+    let hooks = save_stake_info(deps.storage, &account, &user_stake_info, weight_before, env.block.height)?;
 
-    //update user's reward
-    user_stake_info.reward = user_stake_info.reward + (reward_info.cur_sum_index - user_stake_info.index) * user_stake_info.balance / PRECISION;
-    user_stake_info.index = reward_info.cur_sum_index;
-    //update user's balance
-    user_stake_info.balance = user_stake_info.balance - unstake_amount;
-    reward_info.total_stake = reward_info.total_stake - unstake_amount;
-    //Transfer to user
-    let send_msg: CosmosMsg<Empty> = CosmosMsg::Bank(BankMsg::Send {
-        to_address: address,
-        amount: vec![Coin{
-            denom: "orai".to_string(),
-            amount: unstake_amount,
-        }],
+    //Without a treasury or anyone left staking nobody could receive the penalty, the staker keeps it
+    let has_treasury = config.early_exit_penalty.as_ref().is_some_and(|early_exit_penalty| early_exit_penalty.treasury.is_some());
+    if !has_treasury && TOTAL_WEIGHT.load(deps.storage)?.is_zero() {
+        penalty = Uint128::zero();
+    }
+
+    //Queue the unstaked amount until the unbonding period has passed. Delegated funds leave
+    //the validators with the next undelegation batch and are back once the chain released it.
+    let mut unbonding = UnbondingInfo {
+        amount: unstake_amount,
+        release_at: env.block.time.plus_seconds(config.unbonding_period),
+        batch: None,
+    };
+    if let Some(delegation) = &config.delegation {
+        let mut batch = UNDELEGATION_BATCH.may_load(deps.storage)?.unwrap_or_default();
+        batch.amount += unstake_amount;
+        UNDELEGATION_BATCH.save(deps.storage, &batch)?;
+        msgs.extend(send_undelegation_batch(deps.storage, &deps.querier, &env, &config)?);
+
+        //Until it is sent the batch is expected back one unbonding period after its epoch ends
+        let batch_release_at = match SENT_BATCHES.may_load(deps.storage, batch.id)? {
+            Some(release_at) => release_at,
+            None => batch.send_at.max(env.block.time).plus_seconds(delegation.unbonding_period),
+        };
+        unbonding.release_at = unbonding.release_at.max(batch_release_at);
+        unbonding.batch = Some(batch.id);
+    }
+    let release_at = unbonding.release_at;
+    let mut unbondings = UNBONDINGS.may_load(deps.storage, account.clone())?.unwrap_or_default();
+    unbondings.push(UnbondingInfo {
+        amount: unstake_amount - penalty,
+        ..unbonding.clone()
     });
+    UNBONDINGS.save(deps.storage, account, &unbondings)?;
+
+    let mut res = Response::new()
+        .add_messages(msgs)
+        .add_submessages(hooks)
+        .add_attribute("action", "unstake")
+        .add_attribute("amount", unstake_amount)
+        .add_attribute("release_at", release_at.seconds().to_string());
+
+    if !penalty.is_zero() {
+        let destination = match config.early_exit_penalty.and_then(|early_exit_penalty| early_exit_penalty.treasury) {
+            //Undelegated penalties only become transferable with the rest of the unbonding,
+            //the next harvest after that sends them over
+            Some(treasury) if config.delegation.is_some() => {
+                let mut penalties = TREASURY_PENALTIES.may_load(deps.storage, treasury.clone())?.unwrap_or_default();
+                penalties.push(UnbondingInfo {
+                    amount: penalty,
+                    ..unbonding
+                });
+                TREASURY_PENALTIES.save(deps.storage, treasury.clone(), &penalties)?;
+                treasury.to_string()
+            }
+            Some(treasury) => {
+                res = res.add_message(transfer_msg(&config.stake_denom, &treasury, penalty)?);
+                treasury.to_string()
+            }
+            //Undelegated penalties are only credited once the chain released them
+            None if config.delegation.is_some() => {
+                let mut pending = PENDING_PENALTIES.may_load(deps.storage)?.unwrap_or_default();
+                pending.push(UnbondingInfo {
+                    amount: penalty,
+                    ..unbonding
+                });
+                PENDING_PENALTIES.save(deps.storage, &pending)?;
+                "stakers".to_string()
+            }
+            None => {
+                pay_to_stakers(deps.storage, &env, &config.stake_denom, penalty)?;
+                "stakers".to_string()
+            }
+        };
+        res = res
+            .add_attribute("penalty", penalty)
+            .add_attribute("penalty_to", destination);
+    }
+
+    Ok(res)
+}
 
-    //Save
-    USERS.save(deps.storage, account, &user_stake_info)?;
-    REWARD.save(deps.storage, &reward_info)?;
+/// Takes `amount` out of the locks of `stake_info`, soonest to expire first. Returns what was taken,
+/// less than `amount` only when the locks don't hold that much.
+fn break_locks(stake_info: &mut StakeInfo, amount: Uint128) -> Result<Uint128, ContractError> {
+    stake_info.locks.sort_by_key(|lock| lock.unlock_at);
+
+    let mut broken = Uint128::zero();
+    for lock in stake_info.locks.iter_mut() {
+        let take = lock.amount.min(amount - broken);
+        lock.amount -= take;
+        lock.weight = lock.amount.checked_mul_floor(lock.multiplier)?;
+        broken += take;
+        if broken == amount {
+            break;
+        }
+    }
 
-    Ok(Response::new().add_message(send_msg))
+    stake_info.locks.retain(|lock| !lock.amount.is_zero());
+    Ok(broken)
 }
 
-fn update_cur_sum_index(
+/// Pays `amount` to every staker through the reward stream of the stake token, registering
+/// that stream if needed. Without stakers left it stays in the stream's funds that no schedule
+/// promised yet, for the updater to schedule again.
+fn pay_to_stakers(
+    storage: &mut dyn Storage,
+    env: &Env,
+    stake_denom: &Denom,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let asset = asset_key(stake_denom);
+    let mut reward_info = match REWARDS.may_load(storage, &asset)? {
+        Some(reward_info) => reward_info,
+        None => RewardInfo::new(stake_denom.clone(), env.block.time),
+    };
+
+    reward_info.total_funded = reward_info.total_funded.checked_add(amount)?;
+    let total_weight = TOTAL_WEIGHT.load(storage)?;
+    if !total_weight.is_zero() {
+        distribute(&mut reward_info, amount, total_weight)?;
+    }
+
+    REWARDS.save(storage, &asset, &reward_info)?;
+    Ok(())
+}
+
+fn unlock(
     deps: DepsMut,
     env: Env,
+    address: String,
 ) -> Result<Response, ContractError> {
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed: u64 = cur.seconds() - reward_info.last_update.seconds();
-
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
+    let account = deps.api.addr_validate(&address)?;
+    let mut stake_info = USERS.load(deps.storage, account.clone())?;
+
+    //Settle at the boosted weight up to now, then drop the boost
+    let rewards = update_rewards(deps.storage, env.block.time)?;
+    settle_user_rewards(&mut stake_info, &rewards)?;
+    let weight_before = stake_info.weight();
+    let released = release_expired_locks(&mut stake_info, env.block.time);
+    if released.is_zero() {
+        return Err(ContractError::NothingToUnlock{});
     }
 
-    reward_info.last_update = cur;
-    REWARD.save(deps.storage, &reward_info)?;
-    
-    Ok(Response::new())
+    let hooks = save_stake_info(deps.storage, &account, &stake_info, weight_before, env.block.height)?;
+
+    Ok(
+        Response::new()
+        .add_submessages(hooks)
+        .add_attribute("action", "unlock")
+        .add_attribute("account", account)
+        .add_attribute("amount", released)
+    )
 }
 
-fn claim_reward(
+fn withdraw_unbonded(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
 ) -> Result<Response, ContractError> {
-    //Get user
+    ensure_not_paused(deps.storage, "unstake", |pause| pause.unstake)?;
+
     let account = info.sender;
-    let user_address = account.clone().to_string();
+    let unbondings = UNBONDINGS.may_load(deps.storage, account.clone())?.unwrap_or_default();
 
-    //update cur sum index
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed = cur.seconds() - reward_info.last_update.seconds();
+    //Split matured entries from the ones still cooling down. Undelegated coins land at the end
+    //of the block they mature in, within that block the payout can only fail or be fronted by
+    //liquid stake that is back by the next one.
+    let (matured, pending) = split_released(deps.storage, &env, unbondings)?;
 
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
+    let amount: Uint128 = matured.iter().map(|unbonding| unbonding.amount).sum();
+    if amount.is_zero() {
+        return Err(ContractError::NothingToWithdraw{});
     }
 
-    reward_info.last_update = cur;
-    REWARD.save(deps.storage, &reward_info)?;
-    //Update user reward
-    let mut user = USERS.load(deps.storage, account.clone())?;
-    let claim_reward = user.reward + (reward_info.cur_sum_index - user.index) * user.balance / PRECISION;
-    user.index = reward_info.cur_sum_index;
-    user.reward = Uint128::zero();
-    USERS.save(deps.storage, account, &user)?;
-
-    let msg = WasmMsg::Execute { 
-        contract_addr: USDC_ADDRESS.to_string(), 
-        msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
-            recipient: user_address,
-            amount: claim_reward,
-        })?, 
-        funds: vec![], 
-    };
+    if pending.is_empty() {
+        UNBONDINGS.remove(deps.storage, account.clone());
+    } else {
+        UNBONDINGS.save(deps.storage, account.clone(), &pending)?;
+    }
 
-    Ok(Response::new().add_message(msg))
+    //Transfer to user
+    let config = CONFIG.load(deps.storage)?;
+    let send_msg = transfer_msg(&config.stake_denom, &account, amount)?;
+
+    Ok(
+        Response::new()
+        .add_message(send_msg)
+        .add_attribute("action", "withdraw_unbonded")
+        .add_attribute("amount", amount)
+    )
 }
 
-fn update_oracle(
+fn update_cur_sum_index(
     deps: DepsMut,
-    _env: Env,
-    info: MessageInfo,
-    price: u128,
+    env: Env,
 ) -> Result<Response, ContractError> {
-    let account = info.sender;
-    let config = CONFIG.load(deps.storage)?;
+    update_rewards(deps.storage, env.block.time)?;
     
-    if config.admin != account {
-        return Err(ContractError::Unauthorized{});
-    }
-
-    let p = Uint128::from(price);
-    ORACLE.save(deps.storage, &p)?;
-
     Ok(Response::new())
 }
 
-fn update_user_reward(
+/// Pays every pending reward of `account` out to `recipient`
+fn claim_reward(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
-    account: String,
+    account: Addr,
+    recipient: Addr,
 ) -> Result<Response, ContractError> {
-    let acc_addr = deps.api.addr_validate(&account)?;
-
-    //update cur sum index
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed: u64 = cur.seconds() - reward_info.last_update.seconds();
+    ensure_not_paused(deps.storage, "claim", |pause| pause.claim)?;
 
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
+    //update cur sum index && user reward
+    let rewards = update_rewards(deps.storage, env.block.time)?;
+    let mut user = USERS.load(deps.storage, account.clone())?;
+    settle_user_rewards(&mut user, &rewards)?;
+    let weight_before = user.weight();
+    release_expired_locks(&mut user, env.block.time);
+
+    //Pay out every reward asset in one go
+    let mut msgs = vec![];
+    for (user_reward, mut reward_info) in user.rewards.iter_mut().zip(rewards) {
+        if user_reward.reward.is_zero() {
+            continue;
+        }
+        msgs.push(transfer_msg(&reward_info.denom, &recipient, user_reward.reward)?);
+        reward_info.total_claimed += user_reward.reward;
+        REWARDS.save(deps.storage, &user_reward.asset, &reward_info)?;
+        user_reward.reward = Uint128::zero();
     }
+    let hooks = save_stake_info(deps.storage, &account, &user, weight_before, env.block.height)?;
 
-    reward_info.last_update = cur;
-    REWARD.save(deps.storage, &reward_info)?;
-    
-
-    //update user reward
+    Ok(
+        Response::new()
+        .add_messages(msgs)
+        .add_submessages(hooks)
+        .add_attribute("action", "claim_reward")
+        .add_attribute("account", account)
+        .add_attribute("recipient", recipient)
+    )
+}
 
-    let user = USERS.may_load(deps.storage, acc_addr.clone())?;
+fn authorize_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?;
+    let recipient = recipient.map(|recipient| deps.api.addr_validate(&recipient)).transpose()?;
 
-    match user {
-        Some(mut stake_info) => {
-            stake_info.reward = stake_info.reward + (reward_info.cur_sum_index - stake_info.index) * stake_info.balance / PRECISION;
-            stake_info.index = reward_info.cur_sum_index;
-            USERS.save(deps.storage, acc_addr, &stake_info)?;
-        },
-        None => {
-            let stake_info = StakeInfo {
-                balance: Uint128::zero(),
-                reward: Uint128::zero(),
-                index: reward_info.cur_sum_index,
-            };
-            USERS.save(deps.storage, acc_addr, &stake_info)?;
-        }
-    };
+    OPERATORS.save(deps.storage, (info.sender.clone(), operator.clone()), &OperatorGrant { recipient })?;
 
-    Ok(Response::new())
+    Ok(
+        Response::new()
+        .add_attribute("action", "authorize_operator")
+        .add_attribute("owner", info.sender)
+        .add_attribute("operator", operator)
+    )
 }
 
-
-fn update_rps(
+fn revoke_operator(
     deps: DepsMut,
-    env: Env,
     info: MessageInfo,
-    new_rps: u128,
+    operator: String,
 ) -> Result<Response, ContractError> {
-    
-    let config = CONFIG.load(deps.storage)?;
+    let operator = deps.api.addr_validate(&operator)?;
 
-    if info.sender != config.updater {
-        return  Err(ContractError::Unauthorized { });
-    }
+    OPERATORS.remove(deps.storage, (info.sender.clone(), operator.clone()));
 
-    //update cur sum index
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed: u64 = cur.seconds() - reward_info.last_update.seconds();
+    Ok(
+        Response::new()
+        .add_attribute("action", "revoke_operator")
+        .add_attribute("owner", info.sender)
+        .add_attribute("operator", operator)
+    )
+}
 
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
-    }
+fn claim_reward_for(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    let owner = deps.api.addr_validate(&owner)?;
 
-    reward_info.last_update = cur;
-    reward_info.rps = Uint128::from(new_rps);
+    //Operators never pick where the rewards go, the owner did when authorizing them
+    let grant = OPERATORS
+        .may_load(deps.storage, (owner.clone(), info.sender.clone()))?
+        .ok_or(ContractError::Unauthorized{})?;
+    let recipient = grant.recipient.unwrap_or_else(|| owner.clone());
 
-    REWARD.save(deps.storage, &reward_info)?;
-    
-    Ok(Response::new())
+    let res = claim_reward(deps, env, owner, recipient)?;
+    Ok(res.add_attribute("operator", info.sender))
 }
 
-//_________________________________________________________________________
+fn compound(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    ensure_not_paused(deps.storage, "claim", |pause| pause.claim)?;
+    ensure_not_paused(deps.storage, "stake", |pause| pause.stake)?;
 
-//_______________________________
-//|                              |
-//|                              |
-//|            QUERY             |  
-//|                              |
-//|______________________________|
+    let config = CONFIG.load(deps.storage)?;
+    let msgs = harvest_staking_rewards(deps.storage, &deps.querier, &env, &config)?.0;
+    let rewards = update_rewards(deps.storage, env.block.time)?;
+    let account = info.sender;
+    let stake_info = USERS.load(deps.storage, account.clone())?;
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::ViewAPR {} => view_apr(deps, env),
-        QueryMsg::ViewReward { account } => view_reward(deps, env, account),
-        QueryMsg::CheckStakeAmount { address } => check_stake_amount(deps, env, address),
+    let (amount, _, hooks) = compound_rewards(deps.storage, &env, &config, &rewards, &account, stake_info, 0)?;
+    if amount.is_zero() {
+        return Err(ContractError::NothingToCompound{});
     }
-}
 
-fn check_stake_amount(
-    deps: Deps,
-    _env: Env,
-    address: String,
-) -> StdResult<Binary> {
-    let user = USERS.may_load(deps.storage, deps.api.addr_validate(&address)?)?;
-    match user {
-        None => to_json_binary(&ViewStakeAmount{balance: Uint128::zero()}),
-        Some(stake) => to_json_binary(&ViewStakeAmount{balance: stake.balance}),
-    }
+    Ok(
+        Response::new()
+        .add_messages(msgs)
+        .add_messages(delegate_msgs(&config, amount))
+        .add_messages(mint_receipt_msgs(&config, &account, amount)?)
+        .add_submessages(hooks)
+        .add_attribute("action", "compound")
+        .add_attribute("account", account)
+        .add_attribute("amount", amount)
+    )
 }
 
+fn set_auto_compound(
+    deps: DepsMut,
+    info: MessageInfo,
+    enabled: bool,
+) -> Result<Response, ContractError> {
+    let mut stake_info = USERS.load(deps.storage, info.sender.clone())?;
+    stake_info.auto_compound = enabled;
+    USERS.save(deps.storage, info.sender, &stake_info)?;
 
-
-fn view_apr(
-    deps: Deps,
-    _env: Env,
-) -> StdResult<Binary> {
-    let reward_info = REWARD.load(deps.storage)?;
-    let price = ORACLE.load(deps.storage)?;
-    let ts = reward_info.rps * year;
-    let ms = reward_info.total_stake * price;
-    let apr = ts * Uint128::new(100) / ms ;
-    to_json_binary(&ViewAPRResponse{apr})
+    Ok(
+        Response::new()
+        .add_attribute("action", "set_auto_compound")
+        .add_attribute("enabled", enabled.to_string())
+    )
 }
 
-fn view_reward(
-    deps: Deps,
+fn compound_for(
+    deps: DepsMut,
     env: Env,
-    address: String,
-) -> StdResult<Binary> {
-    let account = deps.api.addr_validate(&address)?;
-    let mut reward_info = REWARD.load(deps.storage)?;
-    let cur = env.block.time;
-    let time_passed: u64 = cur.seconds() - reward_info.last_update.seconds();
+    info: MessageInfo,
+    addresses: Vec<String>,
+) -> Result<Response, ContractError> {
+    ensure_not_paused(deps.storage, "claim", |pause| pause.claim)?;
+    ensure_not_paused(deps.storage, "stake", |pause| pause.stake)?;
 
-    if reward_info.total_stake > Uint128::zero() && time_passed > 0 {
-        let reward: Uint128 = reward_info.rps * Uint128::from(time_passed);
-        reward_info.cur_sum_index = reward_info.cur_sum_index + (reward * PRECISION) / reward_info.total_stake;
+    let config = CONFIG.load(deps.storage)?;
+    let mut msgs = harvest_staking_rewards(deps.storage, &deps.querier, &env, &config)?.0;
+    let rewards = update_rewards(deps.storage, env.block.time)?;
+
+    //Addresses that didn't opt in or have nothing to compound are skipped
+    let mut compounded = Uint128::zero();
+    let mut bounty = Uint128::zero();
+    let mut hooks = vec![];
+    for address in addresses {
+        let account = deps.api.addr_validate(&address)?;
+        let stake_info = match USERS.may_load(deps.storage, account.clone())? {
+            Some(stake_info) if stake_info.auto_compound => stake_info,
+            _ => continue,
+        };
+        let (amount, fee, account_hooks) = compound_rewards(deps.storage, &env, &config, &rewards, &account, stake_info, config.compound_bounty_bps)?;
+        if !amount.is_zero() {
+            msgs.extend(mint_receipt_msgs(&config, &account, amount)?);
+        }
+        compounded += amount;
+        bounty += fee;
+        hooks.extend(account_hooks);
     }
 
-    let user_info = USERS.may_load(deps.storage, account)?;
+    if compounded.is_zero() {
+        return Err(ContractError::NothingToCompound{});
+    }
 
-    match user_info {
-        Some(mut stake_info) => {
-            stake_info.reward = stake_info.reward + (reward_info.cur_sum_index - stake_info.index) * stake_info.balance / PRECISION;
-            to_json_binary(&ViewRewardResponse{reward: stake_info.reward})
-        },
-        None => {
-            to_json_binary(&ViewRewardResponse{reward: Uint128::zero()})
-        }
+    let mut res = Response::new()
+        .add_messages(msgs)
+        .add_messages(delegate_msgs(&config, compounded))
+        .add_submessages(hooks);
+    if !bounty.is_zero() {
+        res = res.add_message(transfer_msg(&config.stake_denom, &info.sender, bounty)?);
     }
 
+    Ok(
+        res
+        .add_attribute("action", "compound_for")
+        .add_attribute("amount", compounded)
+        .add_attribute("bounty", bounty)
+    )
 }
 
+/// Moves what `account` earned in the stake token straight into its unlocked stake, keeping
+/// `bounty_bps` of it aside. `rewards` must be accrued up to now. Returns the restaked amount,
+/// the bounty and the stake change hooks.
+fn compound_rewards(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+    rewards: &[RewardInfo],
+    account: &Addr,
+    mut stake_info: StakeInfo,
+    bounty_bps: u16,
+) -> Result<(Uint128, Uint128, Vec<SubMsg>), ContractError> {
+    let asset = asset_key(&config.stake_denom);
+
+    settle_user_rewards(&mut stake_info, rewards)?;
+    let weight_before = stake_info.weight();
+    release_expired_locks(&mut stake_info, env.block.time);
+
+    let user_reward = stake_info.rewards
+        .iter_mut()
+        .find(|user_reward| user_reward.asset == asset)
+        .ok_or(ContractError::StakeNotRewarded{})?;
+    let earned = user_reward.reward;
+    user_reward.reward = Uint128::zero();
+
+    //The rewards never leave the contract, they are claimed and staked in place
+    let bounty = earned.multiply_ratio(bounty_bps, 10_000u128);
+    let amount = earned - bounty;
+    stake_info.balance += amount;
+
+    let hooks = save_stake_info(storage, account, &stake_info, weight_before, env.block.height)?;
+    TOTAL_STAKE.update(storage, env.block.height, |total| -> StdResult<_> { Ok(total.unwrap_or_default() + amount) })?;
+    REWARDS.update(storage, &asset, |reward_info| -> StdResult<_> {
+        let mut reward_info = reward_info.ok_or_else(|| StdError::not_found("RewardInfo"))?;
+        reward_info.total_claimed += earned;
+        Ok(reward_info)
+    })?;
+
+    Ok((amount, bounty, hooks))
+}
 
+/// Moves the stake behind receipts the receipt token reported as transferred. Receipt moves
+/// count as unstaking from one position and staking into another, so either pause stops them.
+fn receipt_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    match &config.receipt_token {
+        Some(receipt_token) if receipt_token == info.sender => {}
+        Some(_) => return Err(ContractError::Unauthorized{}),
+        None => return Err(ContractError::NoReceiptToken{}),
+    }
+    ensure_not_paused(deps.storage, "stake", |pause| pause.stake)?;
+    ensure_not_paused(deps.storage, "unstake", |pause| pause.unstake)?;
 
+    let owner = deps.api.addr_validate(&owner)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let (msgs, hooks) = move_receipts(deps, &env, &config, &owner, &recipient, amount)?;
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
-    unimplemented!()
+    Ok(
+        Response::new()
+        .add_messages(msgs)
+        .add_submessages(hooks)
+        .add_attribute("action", "receipt_transfer")
+        .add_attribute("from", owner)
+        .add_attribute("to", recipient)
+        .add_attribute("amount", amount)
+    )
 }
 
+/// Moves `amount` of stake from `owner` to `recipient`. Both positions are settled at their
+/// old balance first, so nobody has to sync anything once receipts changed hands. Returns the
+/// harvest messages and the stake change hooks.
+fn move_receipts(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    owner: &Addr,
+    recipient: &Addr,
+    amount: Uint128,
+) -> Result<(Vec<CosmosMsg>, Vec<SubMsg>), ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount{});
+    }
 
+    let msgs = harvest_staking_rewards(deps.storage, &deps.querier, env, config)?.0;
+    let rewards = update_rewards(deps.storage, env.block.time)?;
 
-#[cfg(test)]
-mod tests {
-    use std::panic::Location;
+    //The recipient is loaded once the owner is saved, they may be the same
+    let mut sender = USERS.may_load(deps.storage, owner.clone())?.unwrap_or_default();
+    settle_user_rewards(&mut sender, &rewards)?;
+    let weight_before = sender.weight();
+    release_expired_locks(&mut sender, env.block.time);
+    if sender.balance - sender.locked() < amount {
+        return Err(ContractError::NotEnoughBalance{});
+    }
+    sender.balance -= amount;
+    let mut hooks = save_stake_info(deps.storage, owner, &sender, weight_before, env.block.height)?;
 
-    use cosmwasm_std::{attr, from_binary, Api, Empty, Querier, QuerierWrapper, Timestamp};
-    use cosmwasm_std::testing::{mock_dependencies, mock_dependencies_with_balance, mock_env, mock_info};
-    use cw_multi_test::Bank;
-    use serde::de::value::UsizeDeserializer;
-    use crate::contract::{execute, instantiate, query, stake};
-    use crate::msg::{
-         ExecuteMsg, InstantiateMsg, QueryMsg, ViewAPRResponse, ViewBalance, ViewRewardResponse, ViewStakeAmount
-    };
-    use crate::state::{CONFIG, REWARD, USERS, ORACLE, StakeInfo, RewardInfo, Config};
+    let mut receiver = USERS.may_load(deps.storage, recipient.clone())?.unwrap_or_default();
+    settle_user_rewards(&mut receiver, &rewards)?;
+    let weight_before = receiver.weight();
+    release_expired_locks(&mut receiver, env.block.time);
+    receiver.balance += amount;
+    hooks.extend(save_stake_info(deps.storage, recipient, &receiver, weight_before, env.block.height)?);
 
-    use cosmwasm_std::{coin, coins, Addr, BankMsg, CosmosMsg, BankQuery, QueryRequest, Coin};
+    Ok((msgs, hooks))
+}
 
-    pub const address1: &str = "orai1kwyeufzwmwgqwy2aa7ycv4wxaglwd38lkepa05";
-    pub const address2: &str = "orai1acsj7emfhkcn8vzjrm8j8qkdh3czdgutpxdent";
+fn harvest(
+    deps: DepsMut,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let (mut msgs, amount) = harvest_staking_rewards(deps.storage, &deps.querier, &env, &config)?;
+    let undelegations = send_undelegation_batch(deps.storage, &deps.querier, &env, &config)?;
+    if msgs.is_empty() && amount.is_zero() && undelegations.is_empty() {
+        return Err(ContractError::NothingToHarvest{});
+    }
+    msgs.extend(undelegations);
 
-    #[test]
-    fn test() {
+    Ok(
+        Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "harvest")
+        .add_attribute("amount", amount)
+    )
+}
+
+fn fund_native_rewards(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    if info.funds.is_empty() {
+        return Err(ContractError::WrongFundingAmount{});
+    }
+
+    let mut res = Response::new();
+    for coin in info.funds {
+        let funded = fund_rewards(deps.branch(), env.clone(), &coin.denom, coin.amount)?;
+        res = res.add_attributes(funded.attributes);
+    }
+
+    Ok(res)
+}
+
+/// Records `amount` of the reward asset `asset` as available for distribution
+fn fund_rewards(
+    deps: DepsMut,
+    env: Env,
+    asset: &str,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    if amount.is_zero() {
+        return Err(ContractError::WrongFundingAmount{});
+    }
+
+    if !REWARDS.has(deps.storage, asset) {
+        return Err(ContractError::UnknownRewardAsset{ asset: asset.to_string() });
+    }
+
+    //Accrue first so the new funds don't back emission that was already capped
+    update_rewards(deps.storage, env.block.time)?;
+
+    let mut reward_info = REWARDS.load(deps.storage, asset)?;
+    reward_info.total_funded += amount;
+    REWARDS.save(deps.storage, asset, &reward_info)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "fund_rewards")
+        .add_attribute("asset", asset)
+        .add_attribute("amount", amount)
+    )
+}
+
+fn update_oracle(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset: String,
+    price: Decimal,
+) -> Result<Response, ContractError> {
+    ensure_not_paused(deps.storage, "oracle", |pause| pause.oracle)?;
+
+    let account = info.sender;
+    let config = CONFIG.load(deps.storage)?;
+    
+    if config.price_feeder != account {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    if !REWARDS.has(deps.storage, &asset) {
+        return Err(ContractError::UnknownRewardAsset{ asset });
+    }
+
+    //Bound the move from the previous price, the first price is taken as is
+    if let (Some(max_deviation), Some(previous)) = (config.max_price_deviation, ORACLE.may_load(deps.storage, &asset)?) {
+        let change = price.abs_diff(previous.price);
+        if !previous.price.is_zero() && change > previous.price.checked_mul(max_deviation)? {
+            return Err(ContractError::PriceDeviationTooLarge{ max_deviation });
+        }
+    }
+
+    save_price(deps.storage, &asset, price, env.block.time)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "update_oracle")
+        .add_attribute("asset", asset)
+        .add_attribute("price", price.to_string())
+    )
+}
+
+fn update_lock_tiers(
+    deps: DepsMut,
+    info: MessageInfo,
+    lock_tiers: Vec<LockTier>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    validate_lock_tiers(&lock_tiers)?;
+    if config.receipt_token.is_some() && !lock_tiers.is_empty() {
+        return Err(ContractError::ReceiptTokenWithLocks{});
+    }
+    config.lock_tiers = lock_tiers;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "update_lock_tiers")
+    )
+}
+
+fn update_early_exit_penalty(
+    deps: DepsMut,
+    info: MessageInfo,
+    early_exit_penalty: Option<EarlyExitPenaltyMsg>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    config.early_exit_penalty = early_exit_penalty.map(|penalty| validate_early_exit_penalty(deps.as_ref(), penalty)).transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "update_early_exit_penalty")
+        .add_attribute("bps", config.early_exit_penalty.map(|penalty| penalty.bps.to_string()).unwrap_or_default())
+    )
+}
+
+fn update_delegation(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    validators: Vec<String>,
+    unbonding_period: u64,
+    max_entries: Option<u32>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    //Only the chain's own staking token can be delegated
+    let stake_denom = match &config.stake_denom {
+        Denom::Native(denom) => denom.clone(),
+        Denom::Cw20(_) => return Err(ContractError::CannotDelegate{}),
+    };
+    if deps.querier.query_bonded_denom()? != stake_denom {
+        return Err(ContractError::CannotDelegate{});
+    }
+
+    let mut sorted = validators.clone();
+    sorted.sort();
+    sorted.dedup();
+    if validators.is_empty() || sorted.len() != validators.len() {
+        return Err(ContractError::InvalidValidators{});
+    }
+    for validator in validators.iter() {
+        if deps.querier.query_validator(validator)?.is_none() {
+            return Err(ContractError::UnknownValidator{ validator: validator.clone() });
+        }
+    }
+    let max_entries = max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+    if max_entries == 0 {
+        return Err(ContractError::InvalidMaxEntries{});
+    }
+    let delegation_config = DelegationConfig { validators, unbonding_period, max_entries };
+
+    //Redelegations withdraw pending rewards, book them first
+    let mut msgs = harvest_staking_rewards(deps.storage, &deps.querier, &env, &config)?.0;
+
+    //Move delegations off validators that left the set
+    let delegations = deps.querier.query_all_delegations(env.contract.address)?;
+    let mut delegated = Uint128::zero();
+    let mut redelegations = vec![];
+    for delegation in delegations {
+        delegated += delegation.amount.amount;
+        if delegation_config.validators.contains(&delegation.validator) {
+            continue;
+        }
+        for (dst_validator, amount) in split_evenly(&delegation_config.validators, delegation.amount.amount) {
+            redelegations.push(CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator: delegation.validator.clone(),
+                dst_validator,
+                amount: Coin { denom: stake_denom.clone(), amount },
+            }));
+        }
+    }
+
+    //One redelegation per epoch keeps every validator pair within the chain's entry limit
+    if !redelegations.is_empty() {
+        if let Some(last_redelegation) = LAST_REDELEGATION.may_load(deps.storage)? {
+            let next_at = last_redelegation.plus_seconds(delegation_config.epoch());
+            if env.block.time < next_at {
+                return Err(ContractError::RedelegationTooSoon{ next_at: next_at.seconds() });
+            }
+        }
+        LAST_REDELEGATION.save(deps.storage, &env.block.time)?;
+        msgs.extend(redelegations);
+    }
+
+    //Stake still held by the contract joins the validators, unstaked stake waiting for its
+    //batch is still delegated
+    config.delegation = Some(delegation_config);
+    let total_stake = TOTAL_STAKE.load(deps.storage)?;
+    let queued = UNDELEGATION_BATCH.may_load(deps.storage)?.unwrap_or_default().amount;
+    msgs.extend(delegate_msgs(&config, (total_stake + queued).saturating_sub(delegated)));
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "update_delegation")
+        .add_attribute("unbonding_period", unbonding_period.to_string())
+    )
+}
+
+fn update_compound_bounty(
+    deps: DepsMut,
+    info: MessageInfo,
+    bps: u16,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    validate_compound_bounty(bps)?;
+    config.compound_bounty_bps = bps;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "update_compound_bounty")
+        .add_attribute("bps", bps.to_string())
+    )
+}
+
+fn add_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    let hook = deps.api.addr_validate(&addr)?;
+    let mut hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+    if hooks.contains(&hook) {
+        return Err(ContractError::HookAlreadyRegistered{ addr });
+    }
+    hooks.push(hook.clone());
+    HOOKS.save(deps.storage, &hooks)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "add_hook")
+        .add_attribute("hook", hook)
+    )
+}
+
+fn remove_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    let hook = deps.api.addr_validate(&addr)?;
+    let mut hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+    if !hooks.contains(&hook) {
+        return Err(ContractError::HookNotRegistered{ addr });
+    }
+    hooks.retain(|registered| registered != hook);
+    HOOKS.save(deps.storage, &hooks)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "remove_hook")
+        .add_attribute("hook", hook)
+    )
+}
+
+fn validate_compound_bounty(bps: u16) -> Result<(), ContractError> {
+    if bps > MAX_COMPOUND_BOUNTY_BPS {
+        return Err(ContractError::InvalidCompoundBounty{ max_bps: MAX_COMPOUND_BOUNTY_BPS });
+    }
+    Ok(())
+}
+
+fn validate_early_exit_penalty(deps: Deps, penalty: EarlyExitPenaltyMsg) -> Result<EarlyExitPenalty, ContractError> {
+    if penalty.bps > 10_000 {
+        return Err(ContractError::InvalidEarlyExitPenalty{});
+    }
+    Ok(EarlyExitPenalty {
+        bps: penalty.bps,
+        treasury: penalty.treasury.map(|treasury| deps.api.addr_validate(&treasury)).transpose()?,
+    })
+}
+
+fn validate_lock_tiers(lock_tiers: &[LockTier]) -> Result<(), ContractError> {
+    for (i, tier) in lock_tiers.iter().enumerate() {
+        let duplicate = lock_tiers[..i].iter().any(|other| other.duration == tier.duration);
+        if tier.duration == 0 || tier.multiplier < Decimal::one() || duplicate {
+            return Err(ContractError::InvalidLockTiers{});
+        }
+    }
+    Ok(())
+}
+
+fn set_price_oracle(
+    deps: DepsMut,
+    info: MessageInfo,
+    price_oracle: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    config.price_oracle = price_oracle.map(|price_oracle| deps.api.addr_validate(&price_oracle)).transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "set_price_oracle")
+        .add_attribute("price_oracle", config.price_oracle.map(String::from).unwrap_or_default())
+    )
+}
+
+fn update_oracle_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    price_feeder: Option<String>,
+    max_price_age: Option<u64>,
+    max_price_deviation: Option<Decimal>,
+    clear_max_price_deviation: bool,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    if let Some(price_feeder) = price_feeder {
+        config.price_feeder = deps.api.addr_validate(&price_feeder)?;
+    }
+    config.max_price_age = max_price_age.unwrap_or(config.max_price_age);
+    //Left untouched unless a new limit is given or the limit is cleared
+    match (max_price_deviation, clear_max_price_deviation) {
+        (Some(_), true) => return Err(ContractError::ConflictingMaxPriceDeviation{}),
+        (Some(max_price_deviation), false) => config.max_price_deviation = Some(max_price_deviation),
+        (None, true) => config.max_price_deviation = None,
+        (None, false) => {}
+    }
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "update_oracle_config")
+        .add_attribute("price_feeder", config.price_feeder.to_string())
+        .add_attribute("max_price_age", config.max_price_age.to_string())
+        .add_attribute("max_price_deviation", config.max_price_deviation.map_or("none".to_string(), |deviation| deviation.to_string()))
+    )
+}
+
+fn update_user_reward(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    account: String,
+) -> Result<Response, ContractError> {
+    let acc_addr = deps.api.addr_validate(&account)?;
+
+    //update cur sum index
+    let rewards = update_rewards(deps.storage, env.block.time)?;
+
+    //update user reward
+    let mut stake_info = USERS.may_load(deps.storage, acc_addr.clone())?.unwrap_or_default();
+    settle_user_rewards(&mut stake_info, &rewards)?;
+
+    //Expired boosts stop counting as soon as anyone settles the account
+    let weight_before = stake_info.weight();
+    release_expired_locks(&mut stake_info, env.block.time);
+    let hooks = save_stake_info(deps.storage, &acc_addr, &stake_info, weight_before, env.block.height)?;
+
+    Ok(Response::new().add_submessages(hooks))
+}
+
+
+fn add_reward_schedule(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset: String,
+    schedule: RewardSchedule,
+) -> Result<Response, ContractError> {
+    
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.updater {
+        return  Err(ContractError::Unauthorized { });
+    }
+
+    if !REWARDS.has(deps.storage, &asset) {
+        return Err(ContractError::UnknownRewardAsset{ asset });
+    }
+
+    //update cur sum index before the emission changes
+    update_rewards(deps.storage, env.block.time)?;
+
+    let mut reward_info = REWARDS.load(deps.storage, &asset)?;
+    let attrs = vec![
+        ("start", schedule.start.seconds().to_string()),
+        ("end", schedule.end.seconds().to_string()),
+        ("total_amount", schedule.total_amount.to_string()),
+    ];
+    queue_schedule(&mut reward_info, schedule, env.block.time)?;
+    REWARDS.save(deps.storage, &asset, &reward_info)?;
+    
+    Ok(
+        Response::new()
+        .add_attribute("action", "add_reward_schedule")
+        .add_attribute("asset", asset)
+        .add_attributes(attrs)
+    )
+}
+
+/// Appends `schedule` after every window already queued on `reward_info`
+fn queue_schedule(
+    reward_info: &mut RewardInfo,
+    schedule: RewardSchedule,
+    cur: Timestamp,
+) -> Result<(), ContractError> {
+    let queued_until = reward_info.schedules.last().map(|last| last.end).unwrap_or(cur);
+
+    if schedule.end <= schedule.start
+        || schedule.start < cur
+        || schedule.start < queued_until
+        || schedule.total_amount.is_zero()
+    {
+        return Err(ContractError::InvalidRewardSchedule{});
+    }
+
+    reward_info.schedules.push(schedule);
+    Ok(())
+}
+
+fn execute_add_reward_asset(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: UncheckedDenom,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    let asset = add_reward_asset(deps, &env, denom)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "add_reward_asset")
+        .add_attribute("asset", asset)
+    )
+}
+
+/// Admin offers `role` to `address`, replacing any earlier proposal
+fn propose_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    pending: Item<PendingRole>,
+    role: &str,
+    address: String,
+    expiry: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    let proposal = PendingRole {
+        address: deps.api.addr_validate(&address)?,
+        expiry: expiry.unwrap_or_default(),
+    };
+    pending.save(deps.storage, &proposal)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", format!("propose_new_{}", role))
+        .add_attribute("proposed", proposal.address)
+        .add_attribute("expiry", proposal.expiry.to_string())
+    )
+}
+
+/// The proposed holder takes over `role`, stored in the `holder` field of `Config`
+fn accept_role(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pending: Item<PendingRole>,
+    role: &str,
+    holder: fn(&mut Config) -> &mut Addr,
+) -> Result<Response, ContractError> {
+    let proposal = pending.may_load(deps.storage)?.ok_or(ContractError::NoPendingProposal{})?;
+
+    if info.sender != proposal.address {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    if proposal.expiry.is_expired(&env.block) {
+        return Err(ContractError::ProposalExpired{});
+    }
+
+    let mut config = CONFIG.load(deps.storage)?;
+    let previous = std::mem::replace(holder(&mut config), proposal.address.clone());
+    CONFIG.save(deps.storage, &config)?;
+    pending.remove(deps.storage);
+
+    Ok(
+        Response::new()
+        .add_attribute("action", format!("accept_{}", role))
+        .add_attribute("previous", previous)
+        .add_attribute(role, proposal.address)
+    )
+}
+
+fn cancel_role_proposal(
+    deps: DepsMut,
+    info: MessageInfo,
+    pending: Item<PendingRole>,
+    role: &str,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    if pending.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoPendingProposal{});
+    }
+    pending.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("action", format!("cancel_{}_proposal", role)))
+}
+
+fn set_pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    stake: Option<bool>,
+    unstake: Option<bool>,
+    claim: Option<bool>,
+    oracle: Option<bool>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin && Some(&info.sender) != config.guardian.as_ref() {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    let mut pause = PAUSE.load(deps.storage)?;
+    pause.stake = stake.unwrap_or(pause.stake);
+    pause.unstake = unstake.unwrap_or(pause.unstake);
+    pause.claim = claim.unwrap_or(pause.claim);
+    pause.oracle = oracle.unwrap_or(pause.oracle);
+    PAUSE.save(deps.storage, &pause)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "set_pause")
+        .add_attribute("stake", pause.stake.to_string())
+        .add_attribute("unstake", pause.unstake.to_string())
+        .add_attribute("claim", pause.claim.to_string())
+        .add_attribute("oracle", pause.oracle.to_string())
+    )
+}
+
+fn update_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized{});
+    }
+
+    config.guardian = guardian.map(|guardian| deps.api.addr_validate(&guardian)).transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "update_guardian")
+        .add_attribute("guardian", config.guardian.map(String::from).unwrap_or_default())
+    )
+}
+
+/// Unlocks the positions of `stake_info` that expired by `now`, their principal stays
+/// staked without the boost. Rewards have to be settled first. Returns the unlocked amount.
+fn release_expired_locks(stake_info: &mut StakeInfo, now: Timestamp) -> Uint128 {
+    let (expired, locked): (Vec<LockPosition>, Vec<LockPosition>) = stake_info.locks
+        .drain(..)
+        .partition(|lock| lock.unlock_at <= now);
+    stake_info.locks = locked;
+    expired.iter().map(|lock| lock.amount).sum()
+}
+
+/// Saves `stake_info` and moves `TOTAL_WEIGHT` by how far its weight moved from `weight_before`.
+/// A changed balance is snapshotted at `height` for the voting power queries, and the returned
+/// hook messages have to go out with the response.
+fn save_stake_info(
+    storage: &mut dyn Storage,
+    account: &Addr,
+    stake_info: &StakeInfo,
+    weight_before: Uint128,
+    height: u64,
+) -> StdResult<Vec<SubMsg>> {
+    let weight = stake_info.weight();
+    TOTAL_WEIGHT.update(storage, |total| -> StdResult<_> { Ok(total + weight - weight_before) })?;
+    USERS.save(storage, account.clone(), stake_info)?;
+
+    let old_balance = STAKED_BALANCES.may_load(storage, account.clone())?.unwrap_or_default();
+    if old_balance == stake_info.balance {
+        return Ok(vec![]);
+    }
+    STAKED_BALANCES.save(storage, account.clone(), &stake_info.balance, height)?;
+    stake_changed_hooks(storage, account, old_balance, stake_info.balance)
+}
+
+/// Notifies every registered hook that the stake of `addr` moved from `old_balance` to
+/// `new_balance`. A failing hook fails the whole transaction until the admin removes it.
+fn stake_changed_hooks(
+    storage: &dyn Storage,
+    addr: &Addr,
+    old_balance: Uint128,
+    new_balance: Uint128,
+) -> StdResult<Vec<SubMsg>> {
+    let msg = to_json_binary(&StakeChangedHookMsg::StakeChangedHook {
+        addr: addr.clone(),
+        old_balance,
+        new_balance,
+    })?;
+    let hooks = HOOKS.may_load(storage)?.unwrap_or_default();
+    Ok(
+        hooks
+        .into_iter()
+        .map(|hook| SubMsg::new(WasmMsg::Execute {
+            contract_addr: hook.to_string(),
+            msg: msg.clone(),
+            funds: vec![],
+        }))
+        .collect()
+    )
+}
+
+/// Withdraws the staking rewards accumulated by every delegation and books them as rewards
+/// of the stake token, along with the pending penalties released by now. Delegation changes
+/// withdraw rewards on chain anyway, so every handler moving stake runs this before touching
+/// the reward streams. The withdraw messages go out ahead of any other message of the handler,
+/// so the rewards are liquid before anything can be paid from them. Returns the withdraw
+/// messages and the amount credited.
+fn harvest_staking_rewards(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    config: &Config,
+) -> Result<(Vec<CosmosMsg>, Uint128), ContractError> {
+    let released = release_pending_penalties(storage, env)?;
+    if !released.is_zero() {
+        pay_to_stakers(storage, env, &config.stake_denom, released)?;
+    }
+    let mut msgs = release_treasury_penalties(storage, env, &config.stake_denom)?;
+
+    let stake_denom = match (&config.delegation, &config.stake_denom) {
+        (Some(_), Denom::Native(denom)) => denom,
+        _ => return Ok((msgs, released)),
+    };
+
+    let mut harvested = released;
+    for delegation in querier.query_all_delegations(env.contract.address.clone())? {
+        let rewards = querier
+            .query_delegation(env.contract.address.clone(), delegation.validator.clone())?
+            .map(|full_delegation| full_delegation.accumulated_rewards)
+            .unwrap_or_default();
+        let amount: Uint128 = rewards
+            .iter()
+            .filter(|coin| &coin.denom == stake_denom)
+            .map(|coin| coin.amount)
+            .sum();
+        if amount.is_zero() {
+            continue;
+        }
+        msgs.push(CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+            validator: delegation.validator,
+        }));
+        harvested += amount;
+    }
+
+    if harvested > released {
+        pay_to_stakers(storage, env, &config.stake_denom, harvested - released)?;
+    }
+    Ok((msgs, harvested))
+}
+
+/// Drops the pending penalties released by now and returns their sum
+fn release_pending_penalties(storage: &mut dyn Storage, env: &Env) -> StdResult<Uint128> {
+    let pending = PENDING_PENALTIES.may_load(storage)?.unwrap_or_default();
+    let (released, pending) = split_released(storage, env, pending)?;
+    if released.is_empty() {
+        return Ok(Uint128::zero());
+    }
+
+    PENDING_PENALTIES.save(storage, &pending)?;
+    Ok(released.iter().map(|penalty| penalty.amount).sum())
+}
+
+/// Transfers to each treasury the penalties released by now
+fn release_treasury_penalties(storage: &mut dyn Storage, env: &Env, stake_denom: &Denom) -> StdResult<Vec<CosmosMsg>> {
+    let treasuries = TREASURY_PENALTIES
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<Addr>>>()?;
+
+    let mut msgs = vec![];
+    for treasury in treasuries {
+        let penalties = TREASURY_PENALTIES.load(storage, treasury.clone())?;
+        let (released, pending) = split_released(storage, env, penalties)?;
+        if released.is_empty() {
+            continue;
+        }
+
+        if pending.is_empty() {
+            TREASURY_PENALTIES.remove(storage, treasury.clone());
+        } else {
+            TREASURY_PENALTIES.save(storage, treasury.clone(), &pending)?;
+        }
+        let amount: Uint128 = released.iter().map(|penalty| penalty.amount).sum();
+        msgs.push(transfer_msg(stake_denom, &treasury, amount)?);
+    }
+    Ok(msgs)
+}
+
+/// Splits `unbondings` into the ones released by now and the ones still pending. Batched ones
+/// also wait for the chain to release their batch.
+fn split_released(
+    storage: &dyn Storage,
+    env: &Env,
+    unbondings: Vec<UnbondingInfo>,
+) -> StdResult<(Vec<UnbondingInfo>, Vec<UnbondingInfo>)> {
+    let mut released = vec![];
+    let mut pending = vec![];
+    for unbonding in unbondings {
+        let batch_released = match unbonding.batch {
+            Some(batch) => SENT_BATCHES.may_load(storage, batch)?.is_some_and(|release_at| release_at <= env.block.time),
+            None => true,
+        };
+        if batch_released && unbonding.release_at <= env.block.time {
+            released.push(unbonding);
+        } else {
+            pending.push(unbonding);
+        }
+    }
+    Ok((released, pending))
+}
+
+/// Undelegates the open batch once its epoch is over and opens the next one. Nothing happens
+/// when delegation is off or the batch is empty.
+fn send_undelegation_batch(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    env: &Env,
+    config: &Config,
+) -> StdResult<Vec<CosmosMsg>> {
+    let delegation = match &config.delegation {
+        Some(delegation) => delegation,
+        None => return Ok(vec![]),
+    };
+    let batch = UNDELEGATION_BATCH.may_load(storage)?.unwrap_or_default();
+    if batch.amount.is_zero() || batch.send_at > env.block.time {
+        return Ok(vec![]);
+    }
+
+    let msgs = undelegate_msgs(querier, env, config, batch.amount)?;
+    SENT_BATCHES.save(storage, batch.id, &env.block.time.plus_seconds(delegation.unbonding_period))?;
+    UNDELEGATION_BATCH.save(storage, &UndelegationBatch {
+        id: batch.id + 1,
+        amount: Uint128::zero(),
+        send_at: env.block.time.plus_seconds(delegation.epoch()),
+    })?;
+    Ok(msgs)
+}
+
+/// Delegates `amount` of newly pooled stake evenly over the configured validators, nothing
+/// when delegation is off
+fn delegate_msgs(config: &Config, amount: Uint128) -> Vec<CosmosMsg> {
+    let (delegation, stake_denom) = match (&config.delegation, &config.stake_denom) {
+        (Some(delegation), Denom::Native(denom)) => (delegation, denom),
+        _ => return vec![],
+    };
+
+    split_evenly(&delegation.validators, amount)
+        .into_iter()
+        .map(|(validator, amount)| CosmosMsg::Staking(StakingMsg::Delegate {
+            validator,
+            amount: Coin { denom: stake_denom.clone(), amount },
+        }))
+        .collect()
+}
+
+/// Mints `amount` of receipts to `account` for newly credited stake, nothing without a receipt token
+fn mint_receipt_msgs(config: &Config, account: &Addr, amount: Uint128) -> StdResult<Vec<CosmosMsg>> {
+    match &config.receipt_token {
+        Some(receipt_token) => Ok(vec![receipt_msg(receipt_token, Cw20ExecuteMsg::Mint { recipient: account.to_string(), amount })?]),
+        None => Ok(vec![]),
+    }
+}
+
+/// Builds `msg` for the receipt token
+fn receipt_msg(receipt_token: &Addr, msg: Cw20ExecuteMsg) -> StdResult<CosmosMsg> {
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: receipt_token.to_string(),
+        msg: to_json_binary(&msg)?,
+        funds: vec![],
+    }))
+}
+
+/// Undelegates `amount` of unstaked funds with at most one entry per validator, largest
+/// delegations first, nothing when delegation is off
+fn undelegate_msgs(querier: &QuerierWrapper, env: &Env, config: &Config, amount: Uint128) -> StdResult<Vec<CosmosMsg>> {
+    let stake_denom = match (&config.delegation, &config.stake_denom) {
+        (Some(_), Denom::Native(denom)) => denom,
+        _ => return Ok(vec![]),
+    };
+
+    let mut delegations = querier.query_all_delegations(env.contract.address.clone())?;
+    delegations.sort_by_key(|delegation| Reverse(delegation.amount.amount));
+
+    let mut msgs = vec![];
+    let mut remaining = amount;
+    for delegation in delegations {
+        if remaining.is_zero() {
+            break;
+        }
+        let undelegated = delegation.amount.amount.min(remaining);
+        remaining -= undelegated;
+        msgs.push(CosmosMsg::Staking(StakingMsg::Undelegate {
+            validator: delegation.validator,
+            amount: Coin { denom: stake_denom.clone(), amount: undelegated },
+        }));
+    }
+    Ok(msgs)
+}
+
+/// Splits `amount` into one non-zero share per validator, the first ones taking the remainder
+fn split_evenly(validators: &[String], amount: Uint128) -> Vec<(String, Uint128)> {
+    let count = Uint128::from(validators.len() as u128);
+    let share = amount / count;
+    let remainder = (amount % count).u128() as usize;
+
+    validators
+        .iter()
+        .enumerate()
+        .map(|(i, validator)| (validator.clone(), if i < remainder { share + Uint128::one() } else { share }))
+        .filter(|(_, amount)| !amount.is_zero())
+        .collect()
+}
+
+/// Fails with `ContractError::Paused` when the switch picked by `flag` is on
+fn ensure_not_paused(
+    storage: &dyn Storage,
+    operation: &str,
+    flag: fn(&PauseInfo) -> bool,
+) -> Result<(), ContractError> {
+    if flag(&PAUSE.load(storage)?) {
+        return Err(ContractError::Paused{ operation: operation.to_string() });
+    }
+    Ok(())
+}
+
+/// Builds the message paying `amount` of `denom` out of the contract to `recipient`
+fn transfer_msg(denom: &Denom, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+    let msg = match denom {
+        Denom::Native(denom) => CosmosMsg::Bank(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![Coin {
+                denom: denom.clone(),
+                amount,
+            }],
+        }),
+        Denom::Cw20(contract_addr) => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }),
+    };
+    Ok(msg)
+}
+
+//_________________________________________________________________________
+
+//_______________________________
+//|                              |
+//|                              |
+//|            QUERY             |  
+//|                              |
+//|______________________________|
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::ViewAPR { deposit, twap_window } => view_apr(deps, env, deposit, twap_window),
+        QueryMsg::ViewReward { account } => view_reward(deps, env, account),
+        QueryMsg::CheckStakeAmount { address } => check_stake_amount(deps, env, address),
+        QueryMsg::PendingUnbondings { address } => pending_unbondings(deps, env, address),
+        QueryMsg::RewardSchedules { asset } => reward_schedules(deps, env, asset),
+        QueryMsg::RewardReserve {  } => reward_reserve(deps, env),
+        QueryMsg::Config {  } => query_config(deps, env),
+        QueryMsg::PauseState {  } => pause_state(deps, env),
+        QueryMsg::AllStakers { start_after, limit } => all_stakers(deps, env, start_after, limit),
+        QueryMsg::Twap { asset, window_seconds } => query_twap(deps, env, asset, window_seconds),
+        QueryMsg::UserLocks { address } => user_locks(deps, env, address),
+        QueryMsg::Operators { owner, start_after, limit } => operators(deps, env, owner, start_after, limit),
+        QueryMsg::VotingPowerAtHeight { address, height } => voting_power_at_height(deps, env, address, height),
+        QueryMsg::TotalPowerAtHeight { height } => total_power_at_height(deps, env, height),
+        QueryMsg::Info {  } => query_info(deps, env),
+        QueryMsg::Hooks {  } => query_hooks(deps, env),
+    }
+}
+
+fn query_config(
+    deps: Deps,
+    _env: Env,
+) -> StdResult<Binary> {
+    let config = CONFIG.load(deps.storage)?;
+    to_json_binary(&ConfigResponse{
+        admin: config.admin,
+        updater: config.updater,
+        pending_admin: PENDING_ADMIN.may_load(deps.storage)?,
+        pending_updater: PENDING_UPDATER.may_load(deps.storage)?,
+        stake_denom: config.stake_denom,
+        unbonding_period: config.unbonding_period,
+        guardian: config.guardian,
+        price_feeder: config.price_feeder,
+        max_price_age: config.max_price_age,
+        max_price_deviation: config.max_price_deviation,
+        price_oracle: config.price_oracle,
+        lock_tiers: config.lock_tiers,
+        early_exit_penalty: config.early_exit_penalty,
+        compound_bounty_bps: config.compound_bounty_bps,
+        delegation: config.delegation,
+        receipt_token: config.receipt_token,
+    })
+}
+
+fn pause_state(
+    deps: Deps,
+    _env: Env,
+) -> StdResult<Binary> {
+    let pause = PAUSE.load(deps.storage)?;
+    to_json_binary(&PauseStateResponse{pause})
+}
+
+fn user_locks(
+    deps: Deps,
+    _env: Env,
+    address: String,
+) -> StdResult<Binary> {
+    let account = deps.api.addr_validate(&address)?;
+    let locks = USERS.may_load(deps.storage, account)?.map(|stake_info| stake_info.locks).unwrap_or_default();
+    to_json_binary(&UserLocksResponse{locks})
+}
+
+fn operators(
+    deps: Deps,
+    _env: Env,
+    owner: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?.map(Bound::exclusive);
+
+    let operators = OPERATORS
+        .prefix(owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (operator, grant) = item?;
+            Ok(OperatorResponse { operator, recipient: grant.recipient })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_json_binary(&OperatorsResponse{operators})
+}
+
+fn voting_power_at_height(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+) -> StdResult<Binary> {
+    let address = deps.api.addr_validate(&address)?;
+    let height = height.unwrap_or(env.block.height);
+    let power = STAKED_BALANCES.may_load_at_height(deps.storage, address, height)?.unwrap_or_default();
+    to_json_binary(&VotingPowerAtHeightResponse{power, height})
+}
+
+fn total_power_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+) -> StdResult<Binary> {
+    let height = height.unwrap_or(env.block.height);
+    let power = TOTAL_STAKE.may_load_at_height(deps.storage, height)?.unwrap_or_default();
+    to_json_binary(&TotalPowerAtHeightResponse{power, height})
+}
+
+fn query_info(
+    deps: Deps,
+    _env: Env,
+) -> StdResult<Binary> {
+    let info = get_contract_version(deps.storage)?;
+    to_json_binary(&InfoResponse{info})
+}
+
+fn query_hooks(
+    deps: Deps,
+    _env: Env,
+) -> StdResult<Binary> {
+    let hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+    to_json_binary(&HooksResponse{hooks})
+}
+
+fn query_twap(
+    deps: Deps,
+    env: Env,
+    asset: String,
+    window_seconds: u64,
+) -> StdResult<Binary> {
+    let price = load_twap(deps.storage, &asset, env.block.time, window_seconds)?;
+    to_json_binary(&TwapResponse{price, window_seconds})
+}
+
+fn check_stake_amount(
+    deps: Deps,
+    _env: Env,
+    address: String,
+) -> StdResult<Binary> {
+    let user = USERS.may_load(deps.storage, deps.api.addr_validate(&address)?)?;
+    match user {
+        None => to_json_binary(&ViewStakeAmount{balance: Uint128::zero()}),
+        Some(stake) => to_json_binary(&ViewStakeAmount{balance: stake.balance}),
+    }
+}
+
+fn pending_unbondings(
+    deps: Deps,
+    _env: Env,
+    address: String,
+) -> StdResult<Binary> {
+    let account = deps.api.addr_validate(&address)?;
+    let unbondings = UNBONDINGS.may_load(deps.storage, account)?.unwrap_or_default();
+    to_json_binary(&PendingUnbondingsResponse{unbondings})
+}
+
+fn reward_schedules(
+    deps: Deps,
+    env: Env,
+    asset: String,
+) -> StdResult<Binary> {
+    let mut reward_info = REWARDS.load(deps.storage, &asset)?;
+    reward_info.schedules.retain(|schedule| schedule.end > env.block.time);
+    to_json_binary(&RewardSchedulesResponse{schedules: reward_info.schedules})
+}
+
+fn reward_reserve(
+    deps: Deps,
+    env: Env,
+) -> StdResult<Binary> {
+    let reserves = accrued_rewards(deps.storage, env.block.time)?
+        .into_iter()
+        .map(|reward_info| {
+            RewardReserve {
+                reserve: reward_info.total_funded - reward_info.total_distributed,
+                denom: reward_info.denom,
+                total_funded: reward_info.total_funded,
+                total_distributed: reward_info.total_distributed,
+                total_claimed: reward_info.total_claimed,
+            }
+        })
+        .collect();
+
+    to_json_binary(&RewardReserveResponse{reserves})
+}
+
+fn view_apr(
+    deps: Deps,
+    env: Env,
+    deposit: Option<Uint128>,
+    twap_window: Option<u64>,
+) -> StdResult<Binary> {
+    let config = CONFIG.load(deps.storage)?;
+    let total_weight = TOTAL_WEIGHT.load(deps.storage)?;
+    let stake_decimals = token_decimals(deps, &config.stake_denom)?;
+
+    //Price every reward stream that has a fresh oracle price, with its emission rate and decimals
+    let mut streams = vec![];
+    let mut stale_prices = vec![];
+    for reward_info in load_rewards(deps.storage)? {
+        let asset = asset_key(&reward_info.denom);
+        let price_info = match load_price(deps, &env, &config, &asset, twap_window)? {
+            Some(price_info) => price_info,
+            None => continue,
+        };
+        if price_info.updated_at.plus_seconds(config.max_price_age) < env.block.time {
+            stale_prices.push(asset);
+            continue;
+        }
+        let rps = current_rps(&reward_info, env.block.time);
+        streams.push((rps, price_info.price, token_decimals(deps, &reward_info.denom)?));
+    }
+
+    //Sum the APR of every priced stream, undefined without stake
+    let total_apr = |stake: Uint128| -> StdResult<Option<Decimal>> {
+        if stake.is_zero() {
+            return Ok(None);
+        }
+        let mut total = Decimal::zero();
+        for (rps, price, reward_decimals) in streams.iter() {
+            if price.is_zero() {
+                continue;
+            }
+            total = total.checked_add(apr(*rps, stake, *price, stake_decimals, *reward_decimals)?)?;
+        }
+        Ok(Some(total))
+    };
+
+    let cur_apr = total_apr(total_weight)?;
+    let deposit_apr = match deposit {
+        Some(deposit) => total_apr(total_weight.checked_add(deposit)?)?,
+        None => None,
+    };
+
+    to_json_binary(&ViewAPRResponse{
+        apr: cur_apr,
+        apy: cur_apr.map(apy),
+        deposit_apr,
+        stale_prices,
+    })
+}
+
+/// Price of the stake token in `asset`, from the oracle contract when one is set and
+/// answers, otherwise the last pushed price or its average over `twap_window`
+fn load_price(deps: Deps, env: &Env, config: &Config, asset: &str, twap_window: Option<u64>) -> StdResult<Option<PriceInfo>> {
+    if let Some(price_oracle) = &config.price_oracle {
+        let query = QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: price_oracle.to_string(),
+            msg: to_json_binary(&OracleQueryMsg::Price {
+                base: asset_key(&config.stake_denom),
+                quote: asset.to_string(),
+            })?,
+        });
+        if let Ok(res) = deps.querier.query::<OraclePriceResponse>(&query) {
+            return Ok(Some(PriceInfo { price: res.price, updated_at: res.updated_at }));
+        }
+    }
+
+    let price_info = ORACLE.may_load(deps.storage, asset)?;
+    match (price_info, twap_window) {
+        (Some(price_info), Some(window_seconds)) => Ok(Some(PriceInfo {
+            price: load_twap(deps.storage, asset, env.block.time, window_seconds)?,
+            updated_at: price_info.updated_at,
+        })),
+        (price_info, _) => Ok(price_info),
+    }
+}
+
+/// Decimals of `denom`, read from the token for cw20s. Native denoms follow the usual 6.
+fn token_decimals(deps: Deps, denom: &Denom) -> StdResult<u8> {
+    match denom {
+        Denom::Native(_) => Ok(NATIVE_DECIMALS),
+        Denom::Cw20(contract_addr) => {
+            let info: TokenInfoResponse = deps.querier.query_wasm_smart(contract_addr, &Cw20QueryMsg::TokenInfo {})?;
+            Ok(info.decimals)
+        }
+    }
+}
+
+fn view_reward(
+    deps: Deps,
+    env: Env,
+    address: String,
+) -> StdResult<Binary> {
+    let account = deps.api.addr_validate(&address)?;
+    let rewards = accrued_rewards(deps.storage, env.block.time)?;
+
+    let mut stake_info = USERS.may_load(deps.storage, account)?.unwrap_or_default();
+    settle_user_rewards(&mut stake_info, &rewards)?;
+
+    let rewards = stake_info.rewards
+        .into_iter()
+        .zip(rewards)
+        .map(|(user_reward, reward_info)| RewardAmount {
+            denom: reward_info.denom,
+            amount: user_reward.reward,
+        })
+        .collect();
+
+    to_json_binary(&ViewRewardResponse{rewards})
+}
+
+fn all_stakers(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?.map(Bound::exclusive);
+    let rewards = accrued_rewards(deps.storage, env.block.time)?;
+
+    let stakers = USERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (address, stored) = item?;
+            let mut stake_info = stored.clone();
+            settle_user_rewards(&mut stake_info, &rewards)?;
+
+            //Report the index the user was last settled at, not the one settlement just moved it to
+            let rewards = stake_info.rewards
+                .into_iter()
+                .zip(rewards.iter())
+                .map(|(user_reward, reward_info)| StakerReward {
+                    index: stored.rewards
+                        .iter()
+                        .find(|stored_reward| stored_reward.asset == user_reward.asset)
+                        .map(|stored_reward| stored_reward.index)
+                        .unwrap_or_default(),
+                    denom: reward_info.denom.clone(),
+                    pending: user_reward.reward,
+                })
+                .collect();
+
+            Ok(StakerResponse {
+                address,
+                balance: stake_info.balance,
+                auto_compound: stake_info.auto_compound,
+                rewards,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    to_json_binary(&AllStakersResponse{stakers})
+}
+
+
+
+//_________________________________________________________________________
+
+//_______________________________
+//|                              |
+//|                              |
+//|           MIGRATE            |  
+//|                              |
+//|______________________________|
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(mut deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate{ previous_contract: stored.contract });
+    }
+
+    let previous_version: Version = stored.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+
+    if previous_version > new_version {
+        return Err(ContractError::CannotDowngrade{
+            previous_version: stored.version,
+            new_version: CONTRACT_VERSION.to_string(),
+        });
+    }
+
+    //State transforms, oldest first
+    if previous_version < Version::new(0, 2, 0) {
+        migrations::v0_1::migrate(deps.branch(), &env, &msg)?;
+    }
+
+    //New config values
+    if let Some(unbonding_period) = msg.unbonding_period {
+        CONFIG.update(deps.storage, |mut config| -> StdResult<_> {
+            config.unbonding_period = unbonding_period;
+            Ok(config)
+        })?;
+    }
+
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(
+        Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("previous_version", previous_version.to_string())
+        .add_attribute("new_version", new_version.to_string())
+    )
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::{from_json, to_json_binary, ContractResult, Env, StdResult, SystemResult, Timestamp, WasmQuery};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::OwnedDeps;
+    use crate::contract::{execute, instantiate, query};
+    use crate::error::ContractError;
+    use crate::msg::{
+         AllStakersResponse, ConfigResponse, EarlyExitPenaltyMsg, ExecuteMsg, HooksResponse, InfoResponse, OperatorResponse, OperatorsResponse, PauseStateResponse, InstantiateMsg, PendingUnbondingsResponse, QueryMsg, ReceiptTokenMsg, ReceiveMsg, RewardAmount, RewardAssetMsg, RewardReserveResponse, RewardSchedulesResponse, StakeChangedHookMsg, TotalPowerAtHeightResponse, TwapResponse, UserLocksResponse, ViewAPRResponse, ViewRewardResponse, ViewStakeAmount, VotingPowerAtHeightResponse
+    };
+    use crate::state::{LockPosition, LockTier, PendingRole, RewardSchedule, UnbondingInfo, CONFIG, REWARDS, TOTAL_WEIGHT, USERS};
+
+    use cosmwasm_std::{attr, coin, coins, Addr, BankMsg, CosmosMsg, Decimal, DistributionMsg, FullDelegation, StakingMsg, SubMsg, Uint128, Validator, WasmMsg};
+    use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom, Expiration, TokenInfoResponse, UncheckedDenom};
+
+    pub const ADDRESS1: &str = "orai1kwyeufzwmwgqwy2aa7ycv4wxaglwd38lkepa05";
+    pub const ADDRESS2: &str = "orai1acsj7emfhkcn8vzjrm8j8qkdh3czdgutpxdent";
+    pub const USDC_ADDRESS: &str = "orai14x647uadcp3wxav6vvjyq23vtwvkkhqnfy9w4vp77h36qx3gdmhq0ws7zh";
+    pub const LP_TOKEN: &str = "orai1hxm433hnwthrxneyjysvhny539s9kh6s2g2n8y";
+    pub const PARTNER_TOKEN: &str = "orai1lus0f0rhx8s03gdllx2n6vhkmf0536dv57wfge";
+
+    /// Answers every cw20 `TokenInfo` query so `UncheckedDenom::Cw20` validates
+    fn mock_cw20_tokens(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>) {
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { .. } => SystemResult::Ok(ContractResult::Ok(
+                to_json_binary(&TokenInfoResponse {
+                    name: "USD Coin".to_string(),
+                    symbol: "USDC".to_string(),
+                    decimals: 6,
+                    total_supply: Uint128::zero(),
+                })
+                .unwrap(),
+            )),
+            _ => panic!("unexpected query"),
+        });
+    }
+
+    fn fund_cw20(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, env: &Env, token: &str, amount: u128) {
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: ADDRESS1.to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&ReceiveMsg::FundRewards {}).unwrap(),
+        });
+        execute(deps.as_mut(), env.clone(), mock_info(token, &[]), msg).unwrap();
+    }
+
+    /// Schedule emitting `rps` per second for a million seconds from now
+    fn schedule_with_rps(rps: u128) -> RewardSchedule {
+        let start = mock_env().block.time;
+        RewardSchedule {
+            start,
+            end: start.plus_seconds(1_000_000),
+            total_amount: Uint128::new(rps * 1_000_000),
+        }
+    }
+
+    fn default_instantiate_msg() -> InstantiateMsg {
+        InstantiateMsg {
+            admin: Some(ADDRESS1.to_string()),
+            updater: Some(ADDRESS2.to_string()),
+            stake_denom: UncheckedDenom::Native("orai".to_string()),
+            rewards: vec![RewardAssetMsg {
+                denom: UncheckedDenom::Cw20(USDC_ADDRESS.to_string()),
+                schedules: vec![schedule_with_rps(2323)],
+                oracle: Some(Decimal::from_ratio(292u128, 100u128)),
+            }],
+            unbonding_period: None,
+            guardian: None,
+            price_feeder: None,
+            max_price_age: None,
+            max_price_deviation: None,
+            price_oracle: None,
+            lock_tiers: None,
+            early_exit_penalty: None,
+            compound_bounty_bps: None,
+            receipt_token: None,
+        }
+    }
+
+    #[test]
+    fn test() {
         let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
         let mut env = mock_env();
 
 
-        let info = mock_info(address1, &vec![]);
+        let info = mock_info(ADDRESS1, &[]);
 
-        let user1= Addr::unchecked(address1);
-        let user2= Addr::unchecked(address2);
+        let user1= Addr::unchecked(ADDRESS1);
+        let user2= Addr::unchecked(ADDRESS2);
         
         let amount = coins(100000, "orai");
 
         deps.querier.update_balance(&user1, amount.clone());
 
-        deps.querier.update_balance(&user2, amount.clone());
+        deps.querier.update_balance(&user2, amount);
 
 
-        let msg = InstantiateMsg {
-            admin: Some(address1.to_string()),
-            updater: Some(address2.to_string()),
-            rps: Some(2323),
-            oracle: Some(2920000),
-        };
+        let msg = default_instantiate_msg();
 
-        let res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000_000);
         
         let config = CONFIG.load(deps.as_ref().storage).unwrap();
-        eprintln!("{} and {}", config.admin, config.updater);
+        assert_eq!((config.admin, config.updater), (user1.clone(), user2.clone()));
 
-        let reward = REWARD.load(deps.as_ref().storage).unwrap();
-        eprintln!("last update: {}", reward.last_update.seconds());
-        eprintln!("rps: {}", reward.rps);
+        let reward = REWARDS.load(deps.as_ref().storage, USDC_ADDRESS).unwrap();
+        assert_eq!(reward.last_update, env.block.time);
+        assert_eq!(reward.schedules, vec![schedule_with_rps(2323)]);
 
         //////////////////////////////////
         //     Stake   
         /////////////////////////////////
-        let info = mock_info(address2, &coins(10, "orai"));
+        let info = mock_info(ADDRESS2, &coins(10, "orai"));
         let msg = ExecuteMsg::Stake {  };
 
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
 
-        let user2_info = USERS.load(deps.as_ref().storage, user2.clone()).unwrap();
+        let user2_info = USERS.load(deps.as_ref().storage, user2).unwrap();
 
-        eprintln!("user2's balance  {}", user2_info.balance);
+        assert_eq!(user2_info.balance, Uint128::new(10));
 
         env.block.time = Timestamp::from_seconds(reward.last_update.seconds()+100); 
         //Check reward
-        let msg = QueryMsg::ViewReward { account: address2.to_string() };
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
         let bin  = query(deps.as_ref(), env.clone(), msg).unwrap();
-        let res: ViewRewardResponse = from_binary(&bin).unwrap();
-        eprintln!("user2's reward: {}", res.reward);
+        let res: ViewRewardResponse = from_json(bin).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(232300));
 
         //view stake amount
-        let msg = QueryMsg::CheckStakeAmount { address:address2.to_string() };
+        let msg = QueryMsg::CheckStakeAmount { address:ADDRESS2.to_string() };
         let bin = query(deps.as_ref(), env.clone(), msg).unwrap();
-        let res: ViewStakeAmount = from_binary(&bin).unwrap();
-        eprintln!("user2's balance by query: {}", res.balance);
+        let res: ViewStakeAmount = from_json(bin).unwrap();
+        assert_eq!(res.balance, Uint128::new(10));
 
         ////////////////////////////////////////////////////
-        let info = mock_info(address1, &coins(10, "orai"));
+        let info = mock_info(ADDRESS1, &coins(10, "orai"));
         let msg = ExecuteMsg::Stake {  };
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-        let user1_info  = USERS.load(deps.as_ref().storage, user1.clone()).unwrap();
-        eprintln!("user1's balance: {}", user1_info.balance);
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let user1_info  = USERS.load(deps.as_ref().storage, user1).unwrap();
+        assert_eq!(user1_info.balance, Uint128::new(10));
 
         env.block.time = Timestamp::from_seconds(env.block.time.seconds()+100); 
 
         //check reward
-        let msg = QueryMsg::ViewReward { account: address1.to_string() };
+        let msg = QueryMsg::ViewReward { account: ADDRESS1.to_string() };
         let bin  = query(deps.as_ref(), env.clone(), msg).unwrap();
-        let res: ViewRewardResponse = from_binary(&bin).unwrap();
-        eprintln!("user1's reward: {}", res.reward);
-        
+        let res: ViewRewardResponse = from_json(bin).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(116150));
+    }
+
+    #[test]
+    fn denoms_come_from_config() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
 
-        /*//change rps 
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000_000);
 
-        // use address2 to update
-        let info = mock_info(address2, &vec![]);
-        let msg = ExecuteMsg::UpdateRewardPerSecond { new_rps: 1232 };
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-        
-        //view rps
-        let reward = REWARD.load(deps.as_ref().storage).unwrap();
-        eprintln!("rps: {}", reward.rps);
-        //eprintln!("env.block.time  {}", env.block.time.seconds());
-        eprintln!("last update: {}", reward.last_update.seconds());
-        //view APR
-        let msg = QueryMsg::ViewAPR {  };
-        let bin = query(deps.as_ref(), env.clone(), msg).unwrap();
-        let res: ViewAPRResponse = from_binary(&bin).unwrap();
-        eprintln!("Apr: {}", res.apr);
-        
-        // stake more
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        assert_eq!(config.stake_denom, Denom::Native("orai".to_string()));
+        let reward = REWARDS.load(deps.as_ref().storage, USDC_ADDRESS).unwrap();
+        assert_eq!(reward.denom, Denom::Cw20(Addr::unchecked(USDC_ADDRESS)));
+
+        // Only the configured stake denom is accepted
+        let info = mock_info(ADDRESS2, &coins(10, "uatom"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap_err();
+        assert!(matches!(err, ContractError::WrongNativeToken {}));
+
+        let info = mock_info(ADDRESS2, &coins(10, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(100);
+
+        // Unstaked funds are paid back in the stake denom
+        let info = mock_info(ADDRESS2, &[]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 10, early_exit: None }).unwrap();
+        let info = mock_info(ADDRESS2, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::WithdrawUnbonded {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: ADDRESS2.to_string(),
+                amount: coins(10, "orai"),
+            })
+        );
+
+        // Claim pays out of the configured reward token
+        let info = mock_info(ADDRESS2, &[]);
+        let res = execute(deps.as_mut(), env, info, ExecuteMsg::ClaimReward { recipient: None }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: USDC_ADDRESS.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: ADDRESS2.to_string(),
+                    amount: Uint128::new(232300),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+    }
 
-        env.block.time = Timestamp::from_seconds(reward.last_update.seconds() + 10000);
-        
-        let info = mock_info(address2, &coins(30, "orai"));
-        let msg = ExecuteMsg::Stake {  };
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    #[test]
+    fn rewards_are_claimed_to_recipients_and_by_operators() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000);
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &coins(1000, "orai")), ExecuteMsg::Stake {}).unwrap();
+
+        let usdc_transfer = |recipient: &str| CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: USDC_ADDRESS.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer { recipient: recipient.to_string(), amount: Uint128::new(232300) }).unwrap(),
+            funds: vec![],
+        });
+
+        // The owner sends its rewards wherever it likes
+        env.block.time = env.block.time.plus_seconds(100);
+        let msg = ExecuteMsg::ClaimReward { recipient: Some(LP_TOKEN.to_string()) };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, usdc_transfer(LP_TOKEN));
+
+        // Operators need the owner's authorization and pay the recipient it chose
+        let claim_for = ExecuteMsg::ClaimRewardFor { owner: ADDRESS2.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), claim_for.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let msg = ExecuteMsg::AuthorizeOperator { operator: PARTNER_TOKEN.to_string(), recipient: Some(ADDRESS1.to_string()) };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+        let msg = ExecuteMsg::AuthorizeOperator { operator: LP_TOKEN.to_string(), recipient: None };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+
+        let msg = QueryMsg::Operators { owner: ADDRESS2.to_string(), start_after: None, limit: None };
+        let res: OperatorsResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.operators, vec![
+            OperatorResponse { operator: Addr::unchecked(LP_TOKEN), recipient: None },
+            OperatorResponse { operator: Addr::unchecked(PARTNER_TOKEN), recipient: Some(Addr::unchecked(ADDRESS1)) },
+        ]);
+
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), claim_for.clone()).unwrap();
+        assert_eq!(res.messages[0].msg, usdc_transfer(ADDRESS1));
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), claim_for.clone()).unwrap();
+        assert_eq!(res.messages[0].msg, usdc_transfer(ADDRESS2));
+
+        // Revoked operators are locked out again
+        let msg = ExecuteMsg::RevokeOperator { operator: PARTNER_TOKEN.to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), claim_for).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let msg = QueryMsg::Operators { owner: ADDRESS2.to_string(), start_after: None, limit: None };
+        let res: OperatorsResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(res.operators.len(), 1);
+    }
+
+    #[test]
+    fn cw20_stake_denom_rejects_native_funds() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+
+        let mut msg = default_instantiate_msg();
+        msg.stake_denom = UncheckedDenom::Cw20(USDC_ADDRESS.to_string());
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
 
-        let user = USERS.load(deps.as_ref().storage, user2.clone()).unwrap();
-        eprintln!("user2's balance: {}", user.balance);
-        eprintln!("user2's reward: {}", user.reward);
-        //
+        let info = mock_info(ADDRESS2, &coins(10, "orai"));
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap_err();
+        assert!(matches!(err, ContractError::WrongNativeToken {}));
+    }
+
+    #[test]
+    fn cw20_stake_through_receive() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+
+        let mut msg = default_instantiate_msg();
+        msg.stake_denom = UncheckedDenom::Cw20(LP_TOKEN.to_string());
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: ADDRESS2.to_string(),
+            amount: Uint128::new(50),
+            msg: to_json_binary(&ReceiveMsg::Stake {}).unwrap(),
+        });
+
+        // Sends from any other token contract are rejected
+        let info = mock_info(USDC_ADDRESS, &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, receive.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::WrongCw20Token {}));
+
+        let info = mock_info(LP_TOKEN, &[]);
+        execute(deps.as_mut(), env.clone(), info, receive).unwrap();
+        let user = USERS.load(deps.as_ref().storage, Addr::unchecked(ADDRESS2)).unwrap();
+        assert_eq!(user.balance, Uint128::new(50));
+
+        // Unstaking pays the LP token back with a cw20 transfer
+        let info = mock_info(ADDRESS2, &[]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 50, early_exit: None }).unwrap();
+        let info = mock_info(ADDRESS2, &[]);
+        let res = execute(deps.as_mut(), env, info, ExecuteMsg::WithdrawUnbonded {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: LP_TOKEN.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: ADDRESS2.to_string(),
+                    amount: Uint128::new(50),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+    }
 
-        //unstake
-        env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 1000);
+    #[test]
+    fn stake_for_credits_the_recipient() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+
+        let info = mock_info(ADDRESS1, &coins(100, "orai"));
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::StakeFor { recipient: ADDRESS2.to_string() }).unwrap();
+        assert!(res.attributes.contains(&attr("funder", ADDRESS1)));
+        assert!(res.attributes.contains(&attr("beneficiary", ADDRESS2)));
+        let user = USERS.load(deps.as_ref().storage, Addr::unchecked(ADDRESS2)).unwrap();
+        assert_eq!(user.balance, Uint128::new(100));
+
+        // The funder gets no position and can't take the stake back
+        assert!(USERS.may_load(deps.as_ref().storage, Addr::unchecked(ADDRESS1)).unwrap().is_none());
+        let info = mock_info(ADDRESS1, &[]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 100, early_exit: None }).unwrap_err();
+        let info = mock_info(ADDRESS2, &[]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 100, early_exit: None }).unwrap();
+
+        // Same through a cw20 send
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut msg = default_instantiate_msg();
+        msg.stake_denom = UncheckedDenom::Cw20(LP_TOKEN.to_string());
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        let receive = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: ADDRESS1.to_string(),
+            amount: Uint128::new(50),
+            msg: to_json_binary(&ReceiveMsg::StakeFor { recipient: ADDRESS2.to_string() }).unwrap(),
+        });
+        let err = execute(deps.as_mut(), env.clone(), mock_info(USDC_ADDRESS, &[]), receive.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::WrongCw20Token {}));
+        let res = execute(deps.as_mut(), env, mock_info(LP_TOKEN, &[]), receive).unwrap();
+        assert!(res.attributes.contains(&attr("funder", ADDRESS1)));
+        assert!(res.attributes.contains(&attr("beneficiary", ADDRESS2)));
+        let user = USERS.load(deps.as_ref().storage, Addr::unchecked(ADDRESS2)).unwrap();
+        assert_eq!(user.balance, Uint128::new(50));
+    }
 
-        let info = mock_info(address2, &vec![]);
-        let msg = ExecuteMsg::Unstake { amount:20 };
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    #[test]
+    fn unstake_waits_for_unbonding_period() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
 
-        let user = USERS.load(deps.as_ref().storage, user2.clone()).unwrap();
-        eprintln!("user2's balance: {}", user.balance);
-        eprintln!("user2's reward: {}", user.reward);
+        let mut msg = default_instantiate_msg();
+        msg.unbonding_period = Some(1000);
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000_000);
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(10);
+        let info = mock_info(ADDRESS2, &[]);
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Unstake { amount: 0, early_exit: None }).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidZeroAmount {}));
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 100, early_exit: None }).unwrap();
+        assert!(res.messages.is_empty());
+        let release_at = env.block.time.plus_seconds(1000);
+
+        let msg = QueryMsg::PendingUnbondings { address: ADDRESS2.to_string() };
+        let res: PendingUnbondingsResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.unbondings, vec![UnbondingInfo { amount: Uint128::new(100), release_at, batch: None }]);
+
+        // Unbonding funds no longer earn rewards
+        env.block.time = env.block.time.plus_seconds(500);
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(23230));
+
+        let info = mock_info(ADDRESS2, &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::WithdrawUnbonded {}).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToWithdraw {}));
+
+        env.block.time = release_at;
+        let info = mock_info(ADDRESS2, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::WithdrawUnbonded {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: ADDRESS2.to_string(),
+                amount: coins(100, "orai"),
+            })
+        );
+
+        let msg = QueryMsg::PendingUnbondings { address: ADDRESS2.to_string() };
+        let res: PendingUnbondingsResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert!(res.unbondings.is_empty());
+    }
 
-        //view reward
-        env.block.time = Timestamp::from_seconds(env.block.time.seconds() + 2000);
-        let msg = QueryMsg::ViewReward { account: address2.to_string() };
-        let bin = query(deps.as_ref(), env.clone(), msg).unwrap();
-        let res:ViewRewardResponse = from_binary(&bin).unwrap();
+    #[test]
+    fn voting_power_is_snapshotted_by_height() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        let staked_at = env.block.height;
+
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let info = mock_info(ADDRESS1, &coins(300, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        env.block.height += 10;
+        env.block.time = env.block.time.plus_seconds(50);
+        let info = mock_info(ADDRESS2, &[]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 40, early_exit: None }).unwrap();
+
+        // Changes count from the block after the one they happen in
+        for (height, power, total) in [(staked_at, 0, 0), (staked_at + 1, 100, 400), (env.block.height, 100, 400), (env.block.height + 1, 60, 360)] {
+            let msg = QueryMsg::VotingPowerAtHeight { address: ADDRESS2.to_string(), height: Some(height) };
+            let res: VotingPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            assert_eq!(res, VotingPowerAtHeightResponse { power: Uint128::new(power), height });
+
+            let msg = QueryMsg::TotalPowerAtHeight { height: Some(height) };
+            let res: TotalPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            assert_eq!(res, TotalPowerAtHeightResponse { power: Uint128::new(total), height });
+        }
+
+        // Claims leave the balance and its snapshots alone
+        env.block.height += 10;
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::ClaimReward { recipient: None }).unwrap();
+        let msg = QueryMsg::VotingPowerAtHeight { address: ADDRESS1.to_string(), height: None };
+        let res: VotingPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res, VotingPowerAtHeightResponse { power: Uint128::new(300), height: env.block.height });
+
+        // Voting modules also report what they are
+        let res: InfoResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Info {}).unwrap()).unwrap();
+        assert_eq!((res.info.contract.as_str(), res.info.version.as_str()), ("crates.io:och-staking", env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn receipt_transfers_move_voting_power() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+
+        // The receipt token is instantiated along, it is only known once the reply comes in
+        let msg = InstantiateMsg {
+            receipt_token: Some(ReceiptTokenMsg { code_id: 7, name: "Staked ORAI".to_string(), symbol: "SORAI".to_string() }),
+            ..default_instantiate_msg()
+        };
+        let res = instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        assert!(matches!(res.messages[0].msg, CosmosMsg::Wasm(WasmMsg::Instantiate { code_id: 7, .. })));
+        CONFIG.update(deps.as_mut().storage, |mut config| -> StdResult<_> {
+            config.receipt_token = Some(Addr::unchecked(PARTNER_TOKEN));
+            Ok(config)
+        }).unwrap();
+
+        // Staking mints receipts for the stake
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(300, "orai")), ExecuteMsg::Stake {}).unwrap();
+        assert_eq!(res.messages, vec![SubMsg::new(WasmMsg::Execute {
+            contract_addr: PARTNER_TOKEN.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Mint { recipient: ADDRESS1.to_string(), amount: Uint128::new(300) }).unwrap(),
+            funds: vec![],
+        })]);
+        let msg = ExecuteMsg::AddHook { addr: LP_TOKEN.to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        // Only the receipt token reports transfers
+        env.block.height += 1;
+        let msg = ExecuteMsg::ReceiptTransfer { owner: ADDRESS1.to_string(), recipient: ADDRESS2.to_string(), amount: Uint128::new(100) };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let res = execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), msg).unwrap();
+        let hooks: Vec<StakeChangedHookMsg> = res.messages
+            .iter()
+            .map(|msg| match &msg.msg {
+                CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => from_json(msg).unwrap(),
+                msg => panic!("unexpected message {:?}", msg),
+            })
+            .collect();
+        assert_eq!(hooks, vec![
+            StakeChangedHookMsg::StakeChangedHook { addr: Addr::unchecked(ADDRESS1), old_balance: Uint128::new(300), new_balance: Uint128::new(200) },
+            StakeChangedHookMsg::StakeChangedHook { addr: Addr::unchecked(ADDRESS2), old_balance: Uint128::zero(), new_balance: Uint128::new(100) },
+        ]);
+
+        // Both sides are snapshotted by the transfer itself, the total doesn't move
+        for (height, power1, power2) in [(env.block.height, 300, 0), (env.block.height + 1, 200, 100)] {
+            for (address, power) in [(ADDRESS1, power1), (ADDRESS2, power2)] {
+                let msg = QueryMsg::VotingPowerAtHeight { address: address.to_string(), height: Some(height) };
+                let res: VotingPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+                assert_eq!(res.power, Uint128::new(power));
+            }
+            let msg = QueryMsg::TotalPowerAtHeight { height: Some(height) };
+            let res: TotalPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            assert_eq!(res.power, Uint128::new(300));
+        }
+    }
+
+    #[test]
+    fn hooks_are_notified_of_stake_changes() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+
+        let msg = ExecuteMsg::AddHook { addr: PARTNER_TOKEN.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::HookAlreadyRegistered { .. }));
+
+        let res: HooksResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Hooks {}).unwrap()).unwrap();
+        assert_eq!(res.hooks, vec![Addr::unchecked(PARTNER_TOKEN)]);
+
+        let hook_msg = |old_balance: u128, new_balance: u128| SubMsg::new(WasmMsg::Execute {
+            contract_addr: PARTNER_TOKEN.to_string(),
+            msg: to_json_binary(&StakeChangedHookMsg::StakeChangedHook {
+                addr: Addr::unchecked(ADDRESS2),
+                old_balance: Uint128::new(old_balance),
+                new_balance: Uint128::new(new_balance),
+            }).unwrap(),
+            funds: vec![],
+        });
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        assert_eq!(res.messages, vec![hook_msg(0, 100)]);
+
+        let info = mock_info(ADDRESS2, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unstake { amount: 30, early_exit: None }).unwrap();
+        assert_eq!(res.messages, vec![hook_msg(100, 70)]);
+
+        // Claims don't move the balance, only the reward is paid
+        let info = mock_info(ADDRESS2, &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::ClaimReward { recipient: None }).unwrap();
+        assert!(res.messages.iter().all(|msg| !matches!(&msg.msg, CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) if contract_addr == PARTNER_TOKEN)));
+
+        let msg = ExecuteMsg::RemoveHook { addr: PARTNER_TOKEN.to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg.clone()).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::HookNotRegistered { .. }));
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        let res = execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap();
+        assert!(res.messages.is_empty());
+    }
+
+    #[test]
+    fn multiple_reward_assets() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+
+        let mut msg = default_instantiate_msg();
+        msg.rewards.push(RewardAssetMsg {
+            denom: UncheckedDenom::Cw20(PARTNER_TOKEN.to_string()),
+            schedules: vec![schedule_with_rps(100)],
+            oracle: None,
+        });
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000_000);
+        fund_cw20(&mut deps, &env, PARTNER_TOKEN, 10_000_000_000);
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Assets can only be registered once
+        let msg = ExecuteMsg::AddRewardAsset { denom: UncheckedDenom::Cw20(PARTNER_TOKEN.to_string()) };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::DuplicateRewardAsset { .. }));
+
+        // A stream added later only pays from the moment it is registered
+        env.block.time = env.block.time.plus_seconds(10);
+        let msg = ExecuteMsg::AddRewardAsset { denom: UncheckedDenom::Native("uosmo".to_string()) };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        let schedule = RewardSchedule {
+            start: env.block.time,
+            end: env.block.time.plus_seconds(10),
+            total_amount: Uint128::new(70),
+        };
+        let msg = ExecuteMsg::AddRewardSchedule { asset: "uosmo".to_string(), schedule };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+        let info = mock_info(ADDRESS1, &coins(70, "uosmo"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards {}).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(10);
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(
+            res.rewards,
+            vec![
+                RewardAmount { denom: Denom::Cw20(Addr::unchecked(USDC_ADDRESS)), amount: Uint128::new(46460) },
+                RewardAmount { denom: Denom::Cw20(Addr::unchecked(PARTNER_TOKEN)), amount: Uint128::new(2000) },
+                RewardAmount { denom: Denom::Native("uosmo".to_string()), amount: Uint128::new(70) },
+            ]
+        );
+
+        // One claim pays every asset
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::ClaimReward { recipient: None }).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send { to_address: ADDRESS2.to_string(), amount: coins(70, "uosmo") })
+        );
+
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert!(res.rewards.iter().all(|reward| reward.amount.is_zero()));
+    }
+
+    #[test]
+    fn reward_schedules_bound_emission() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        let start = env.block.time;
+
+        let mut msg = default_instantiate_msg();
+        msg.rewards[0].schedules = vec![RewardSchedule {
+            start,
+            end: start.plus_seconds(100),
+            total_amount: Uint128::new(1000),
+        }];
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000_000);
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Overlapping windows are rejected, back to back ones are queued
+        let overlapping = RewardSchedule {
+            start: start.plus_seconds(50),
+            end: start.plus_seconds(150),
+            total_amount: Uint128::new(500),
+        };
+        let msg = ExecuteMsg::AddRewardSchedule { asset: USDC_ADDRESS.to_string(), schedule: overlapping };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidRewardSchedule {}));
+
+        let next = RewardSchedule {
+            start: start.plus_seconds(200),
+            end: start.plus_seconds(300),
+            total_amount: Uint128::new(500),
+        };
+        let msg = ExecuteMsg::AddRewardSchedule { asset: USDC_ADDRESS.to_string(), schedule: next.clone() };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+
+        // Nothing is emitted in the gap between the two windows
+        env.block.time = start.plus_seconds(250);
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(1250));
+
+        let msg = QueryMsg::RewardSchedules { asset: USDC_ADDRESS.to_string() };
+        let res: RewardSchedulesResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.schedules, vec![next]);
+
+        // Emission stops at the end of the last window
+        env.block.time = start.plus_seconds(1000);
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::UpdateCurSumIndex {}).unwrap();
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(1500));
+
+        let reward = REWARDS.load(deps.as_ref().storage, USDC_ADDRESS).unwrap();
+        assert!(reward.schedules.is_empty());
+    }
+
+    #[test]
+    fn emission_is_capped_by_funding() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Only tokens of a registered reward asset can fund it
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: ADDRESS1.to_string(),
+            amount: Uint128::new(1),
+            msg: to_json_binary(&ReceiveMsg::FundRewards {}).unwrap(),
+        });
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::UnknownRewardAsset { .. }));
+
+        // 10 seconds of emission are funded, the schedule wants 2323 per second
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 23230);
+        env.block.time = env.block.time.plus_seconds(100);
+
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(23230));
+
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::ClaimReward { recipient: None }).unwrap();
+
+        // Topping up resumes emission from that point on
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 100_000);
+        env.block.time = env.block.time.plus_seconds(10);
+
+        let res: RewardReserveResponse = from_json(query(deps.as_ref(), env, QueryMsg::RewardReserve {}).unwrap()).unwrap();
+        let reserve = &res.reserves[0];
+        assert_eq!(reserve.total_funded, Uint128::new(123230));
+        assert_eq!(reserve.total_distributed, Uint128::new(46460));
+        assert_eq!(reserve.total_claimed, Uint128::new(23230));
+        assert_eq!(reserve.reserve, Uint128::new(76770));
+    }
+
+    #[test]
+    fn small_stakes_accrue_alongside_large_ones() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 2_323_000);
+        let info = mock_info(ADDRESS1, &coins(100_000_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let info = mock_info(ADDRESS2, &coins(100_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Each token earns ~2.3e-8 per 1000 seconds, below what a 6 digit index could hold
+        env.block.time = env.block.time.plus_seconds(1000);
+
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(2));
+
+        let msg = QueryMsg::ViewReward { account: ADDRESS1.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(2_322_997));
+    }
+
+    #[test]
+    fn apr_is_decimal_and_defined_without_stake() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+
+        // Nothing staked yet, a 1000 ORAI deposit would get 2323 uusdc/s over 2920 USDC of stake
+        let msg = QueryMsg::ViewAPR { deposit: Some(Uint128::new(1_000_000_000)), twap_window: None };
+        let res: ViewAPRResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.apr, None);
+        assert_eq!(res.apy, None);
+        assert_eq!(res.deposit_apr, Some(Decimal::from_ratio(250884u128, 10000u128)));
+
+        let info = mock_info(ADDRESS2, &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        let msg = QueryMsg::ViewAPR { deposit: Some(Uint128::new(1_000_000_000)), twap_window: None };
+        let res: ViewAPRResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(res.apr, Some(Decimal::from_ratio(250884u128, 10000u128)));
+        assert!(res.apy.unwrap() > res.apr.unwrap());
+        assert_eq!(res.deposit_apr, Some(Decimal::from_ratio(125442u128, 10000u128)));
+    }
+
+    #[test]
+    fn oracle_prices_come_from_the_feeder_within_limits() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        let msg = InstantiateMsg {
+            price_feeder: Some(PARTNER_TOKEN.to_string()),
+            max_price_age: Some(3600),
+            max_price_deviation: Some(Decimal::percent(10)),
+            ..default_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        let info = mock_info(ADDRESS2, &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Only the feeder pushes prices, even the admin is refused
+        let update = |price: u128| ExecuteMsg::UpdateOracle {
+            asset: USDC_ADDRESS.to_string(),
+            price: Decimal::from_ratio(price, 100u128),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), update(300)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // 2.92 -> 3.30 is a 13% move
+        let err = execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), update(330)).unwrap_err();
+        assert!(matches!(err, ContractError::PriceDeviationTooLarge { .. }));
+
+        env.block.time = env.block.time.plus_seconds(1800);
+        execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), update(300)).unwrap();
+
+        // Past the max age the price is reported stale instead of used
+        env.block.time = env.block.time.plus_seconds(3601);
+        let msg = QueryMsg::ViewAPR { deposit: None, twap_window: None };
+        let res: ViewAPRResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.apr, Some(Decimal::zero()));
+        assert_eq!(res.stale_prices, vec![USDC_ADDRESS.to_string()]);
+
+        // The admin hands the feeder role over and widens the limits
+        let msg = ExecuteMsg::UpdateOracleConfig {
+            price_feeder: Some(ADDRESS1.to_string()),
+            max_price_age: None,
+            max_price_deviation: Some(Decimal::percent(50)),
+            clear_max_price_deviation: None,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(PARTNER_TOKEN, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), update(400)).unwrap();
+
+        let res: ViewAPRResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::ViewAPR { deposit: None, twap_window: None }).unwrap()).unwrap();
+        assert!(res.stale_prices.is_empty());
+        let res: ConfigResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(res.price_feeder, Addr::unchecked(ADDRESS1));
+        assert_eq!(res.max_price_age, 3600);
+        assert_eq!(res.max_price_deviation, Some(Decimal::percent(50)));
+
+        // Leaving the limit out keeps it, clearing it lets any move through
+        let msg = ExecuteMsg::UpdateOracleConfig {
+            price_feeder: None,
+            max_price_age: None,
+            max_price_deviation: Some(Decimal::percent(20)),
+            clear_max_price_deviation: Some(true),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::ConflictingMaxPriceDeviation {}));
+        let msg = ExecuteMsg::UpdateOracleConfig {
+            price_feeder: None,
+            max_price_age: Some(7200),
+            max_price_deviation: None,
+            clear_max_price_deviation: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), update(1000)).unwrap_err();
+        assert!(matches!(err, ContractError::PriceDeviationTooLarge { .. }));
+
+        let msg = ExecuteMsg::UpdateOracleConfig {
+            price_feeder: None,
+            max_price_age: None,
+            max_price_deviation: None,
+            clear_max_price_deviation: Some(true),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), update(1000)).unwrap();
+        let res: ConfigResponse = from_json(query(deps.as_ref(), env, QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(res.max_price_age, 7200);
+        assert_eq!(res.max_price_deviation, None);
+    }
+
+    #[test]
+    fn twap_smooths_pushed_prices() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        let info = mock_info(ADDRESS2, &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // 2.92 for 100 seconds, then a spike to 8.76 for 100 seconds
+        env.block.time = env.block.time.plus_seconds(100);
+        let msg = ExecuteMsg::UpdateOracle { asset: USDC_ADDRESS.to_string(), price: Decimal::from_ratio(876u128, 100u128) };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        env.block.time = env.block.time.plus_seconds(100);
+
+        let msg = QueryMsg::Twap { asset: USDC_ADDRESS.to_string(), window_seconds: 200 };
+        let res: TwapResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.price, Decimal::from_ratio(584u128, 100u128));
+
+        // Older than the first price is not covered
+        let msg = QueryMsg::Twap { asset: USDC_ADDRESS.to_string(), window_seconds: 201 };
+        assert!(query(deps.as_ref(), env.clone(), msg).is_err());
+
+        // The APR follows the average rather than the spike
+        let msg = QueryMsg::ViewAPR { deposit: None, twap_window: Some(200) };
+        let res: ViewAPRResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.apr, Some(Decimal::from_ratio(125442u128, 10000u128)));
+        let msg = QueryMsg::ViewAPR { deposit: None, twap_window: None };
+        let res: ViewAPRResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(res.apr, Some(Decimal::from_ratio(83628u128, 10000u128)));
+    }
+
+    #[test]
+    fn locked_stake_earns_boosted_rewards_until_expiry() {
+        const DAY: u64 = 86400;
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        let msg = InstantiateMsg {
+            lock_tiers: Some(vec![
+                LockTier { duration: 30 * DAY, multiplier: Decimal::percent(150) },
+                LockTier { duration: 90 * DAY, multiplier: Decimal::percent(200) },
+            ]),
+            ..default_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000);
+
+        // Only configured durations can be locked for
+        let info = mock_info(ADDRESS1, &coins(1000, "orai"));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::StakeLocked { duration: DAY }).unwrap_err();
+        assert!(matches!(err, ContractError::UnknownLockTier { .. }));
+
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::StakeLocked { duration: 90 * DAY }).unwrap();
+        let info = mock_info(ADDRESS2, &coins(1000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // The 2x lock earns two thirds of the emission
+        env.block.time = env.block.time.plus_seconds(100);
+        let msg = QueryMsg::ViewReward { account: ADDRESS1.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(154866));
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(77433));
+
+        // Locked principal stays put before expiry
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::Unstake { amount: 1, early_exit: None }).unwrap_err();
+        assert!(matches!(err, ContractError::StakeLocked {}));
+        let unlock = ExecuteMsg::Unlock { address: ADDRESS1.to_string() };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), unlock.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToUnlock {}));
+
+        let msg = QueryMsg::UserLocks { address: ADDRESS1.to_string() };
+        let res: UserLocksResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.locks, vec![LockPosition {
+            amount: Uint128::new(1000),
+            multiplier: Decimal::percent(200),
+            weight: Uint128::new(2000),
+            unlock_at: mock_env().block.time.plus_seconds(90 * DAY),
+        }]);
+
+        // Anyone drops the boost once the lock expired, then the principal is free
+        env.block.time = mock_env().block.time.plus_seconds(90 * DAY);
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), unlock).unwrap();
+        let msg = QueryMsg::UserLocks { address: ADDRESS1.to_string() };
+        let res: UserLocksResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert!(res.locks.is_empty());
+        execute(deps.as_mut(), env, mock_info(ADDRESS1, &[]), ExecuteMsg::Unstake { amount: 1000, early_exit: None }).unwrap();
+    }
+
+    #[test]
+    fn expired_lock_boost_stops_while_its_owner_is_idle() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        let msg = InstantiateMsg {
+            lock_tiers: Some(vec![LockTier { duration: 1000, multiplier: Decimal::percent(200) }]),
+            ..default_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000);
+
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(1000, "orai")), ExecuteMsg::StakeLocked { duration: 1000 }).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &coins(1000, "orai")), ExecuteMsg::Stake {}).unwrap();
+
+        let reward = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, env: &Env, account: &str| {
+            let msg = QueryMsg::ViewReward { account: account.to_string() };
+            let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            res.rewards[0].amount
+        };
+
+        // The lock expired long ago but its owner never came back, the other staker settles it
+        env.block.time = mock_env().block.time.plus_seconds(1500);
+        let msg = ExecuteMsg::UpdateUserReward { account: ADDRESS1.to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+        let msg = QueryMsg::UserLocks { address: ADDRESS1.to_string() };
+        let res: UserLocksResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert!(res.locks.is_empty());
+
+        // From here on both earn alike, 2323 per second split in two
+        let before = (reward(&deps, &env, ADDRESS1), reward(&deps, &env, ADDRESS2));
+        env.block.time = env.block.time.plus_seconds(100);
+        let after = (reward(&deps, &env, ADDRESS1), reward(&deps, &env, ADDRESS2));
+        assert_eq!(after.0 - before.0, Uint128::new(116150));
+        assert_eq!(after.1 - before.1, Uint128::new(116150));
+
+        // Claiming drops an expired boost as well
+        env.block.time = mock_env().block.time.plus_seconds(3000);
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &coins(1000, "orai")), ExecuteMsg::StakeLocked { duration: 1000 }).unwrap();
+        env.block.time = env.block.time.plus_seconds(1000);
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::ClaimReward { recipient: None }).unwrap();
+        let msg = QueryMsg::UserLocks { address: ADDRESS2.to_string() };
+        let res: UserLocksResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert!(res.locks.is_empty());
+        assert_eq!(TOTAL_WEIGHT.load(&deps.storage).unwrap(), Uint128::new(3000));
+    }
+
+    #[test]
+    fn early_exit_pays_a_penalty_to_stakers_or_treasury() {
+        const DAY: u64 = 86400;
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+        let msg = InstantiateMsg {
+            lock_tiers: Some(vec![LockTier { duration: 30 * DAY, multiplier: Decimal::percent(150) }]),
+            early_exit_penalty: Some(EarlyExitPenaltyMsg { bps: 1000, treasury: None }),
+            ..default_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(1000, "orai")), ExecuteMsg::StakeLocked { duration: 30 * DAY }).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(500, "orai")), ExecuteMsg::Stake {}).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &coins(1000, "orai")), ExecuteMsg::Stake {}).unwrap();
+
+        // Locks stay binding unless the staker opts into the penalty
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::Unstake { amount: 700, early_exit: None }).unwrap_err();
+        assert!(matches!(err, ContractError::StakeLocked {}));
+
+        // The unlocked 500 goes first, 10% of the 200 taken out of the lock is kept
+        let msg = ExecuteMsg::Unstake { amount: 700, early_exit: Some(true) };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        assert!(res.messages.is_empty());
+        assert!(res.attributes.contains(&attr("penalty", "20")));
+        assert!(res.attributes.contains(&attr("penalty_to", "stakers")));
+
+        let msg = QueryMsg::PendingUnbondings { address: ADDRESS1.to_string() };
+        let res: PendingUnbondingsResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.unbondings[0].amount, Uint128::new(680));
+        let msg = QueryMsg::UserLocks { address: ADDRESS1.to_string() };
+        let res: UserLocksResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!((res.locks[0].amount, res.locks[0].weight), (Uint128::new(800), Uint128::new(1200)));
+
+        // Remaining weight is 1200 + 1000, the penalty is paid in the stake token
+        let orai = |account: &str| {
+            let msg = QueryMsg::ViewReward { account: account.to_string() };
+            let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+            res.rewards.into_iter().find(|reward| reward.denom == Denom::Native("orai".to_string())).unwrap().amount
+        };
+        assert_eq!(orai(ADDRESS1), Uint128::new(10));
+        assert_eq!(orai(ADDRESS2), Uint128::new(9));
+
+        // Only the admin sets the penalty, which can't exceed the principal
+        let msg = ExecuteMsg::UpdateEarlyExitPenalty { early_exit_penalty: Some(EarlyExitPenaltyMsg { bps: 10_001, treasury: None }) };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidEarlyExitPenalty {}));
+        let msg = ExecuteMsg::UpdateEarlyExitPenalty { early_exit_penalty: Some(EarlyExitPenaltyMsg { bps: 1000, treasury: Some(LP_TOKEN.to_string()) }) };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        // With a treasury the penalty is sent there
+        let msg = ExecuteMsg::Unstake { amount: 100, early_exit: Some(true) };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: LP_TOKEN.to_string(), amount: coins(10, "orai") }));
+        assert!(res.attributes.contains(&attr("penalty_to", LP_TOKEN)));
+
+        // Without a penalty configured locks are binding again
+        let msg = ExecuteMsg::UpdateEarlyExitPenalty { early_exit_penalty: None };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        let err = execute(deps.as_mut(), env, mock_info(ADDRESS1, &[]), ExecuteMsg::Unstake { amount: 100, early_exit: Some(true) }).unwrap_err();
+        assert!(matches!(err, ContractError::StakeLocked {}));
+    }
+
+    #[test]
+    fn early_exit_penalty_is_waived_without_anyone_to_pay() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+        let msg = InstantiateMsg {
+            lock_tiers: Some(vec![LockTier { duration: 1000, multiplier: Decimal::percent(150) }]),
+            early_exit_penalty: Some(EarlyExitPenaltyMsg { bps: 1000, treasury: None }),
+            ..default_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(1000, "orai")), ExecuteMsg::StakeLocked { duration: 1000 }).unwrap();
+
+        // The last staker leaving has nobody to pay the penalty to and unbonds everything
+        let msg = ExecuteMsg::Unstake { amount: 1000, early_exit: Some(true) };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        assert!(!res.attributes.iter().any(|attribute| attribute.key == "penalty"));
+        let msg = QueryMsg::PendingUnbondings { address: ADDRESS1.to_string() };
+        let res: PendingUnbondingsResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.unbondings[0].amount, Uint128::new(1000));
+        let msg = QueryMsg::RewardReserve {};
+        let res: RewardReserveResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert!(res.reserves.iter().all(|reserve| reserve.denom != Denom::Native("orai".to_string())));
+    }
+
+    #[test]
+    fn rewards_in_the_stake_token_compound_in_place() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let msg = InstantiateMsg {
+            rewards: vec![RewardAssetMsg {
+                denom: UncheckedDenom::Native("orai".to_string()),
+                schedules: vec![schedule_with_rps(100)],
+                oracle: None,
+            }],
+            compound_bounty_bps: Some(100),
+            ..default_instantiate_msg()
+        };
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(100_000_000, "orai")), ExecuteMsg::FundRewards {}).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(1000, "orai")), ExecuteMsg::Stake {}).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &coins(1000, "orai")), ExecuteMsg::Stake {}).unwrap();
+
+        // Half of 10 seconds at 100 per second is restaked without any transfer
+        env.block.time = env.block.time.plus_seconds(10);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::Compound {}).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(USERS.load(deps.as_ref().storage, Addr::unchecked(ADDRESS1)).unwrap().balance, Uint128::new(1500));
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::Compound {}).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToCompound {}));
+
+        // Keepers only compound opted in stakers
+        let compound_for = ExecuteMsg::CompoundFor { addresses: vec![ADDRESS1.to_string(), ADDRESS2.to_string()] };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), compound_for.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToCompound {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::SetAutoCompound { enabled: true }).unwrap();
+
+        // 500 + 1000 * 1000 / 2500 earned, 1% of it goes to the keeper
+        env.block.time = env.block.time.plus_seconds(10);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), compound_for).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Bank(BankMsg::Send { to_address: LP_TOKEN.to_string(), amount: coins(9, "orai") }));
+        let stake_info = USERS.load(deps.as_ref().storage, Addr::unchecked(ADDRESS2)).unwrap();
+        assert_eq!((stake_info.balance, stake_info.rewards[0].reward), (Uint128::new(1891), Uint128::zero()));
+        let msg = QueryMsg::ViewReward { account: ADDRESS1.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(600));
+
+        let reward_info = REWARDS.load(deps.as_ref().storage, "orai").unwrap();
+        assert_eq!(reward_info.total_claimed, Uint128::new(1400));
+
+        // Only the admin sets the bounty, and only a small one
+        let msg = ExecuteMsg::UpdateCompoundBounty { bps: 1001 };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCompoundBounty { .. }));
+        let err = execute(deps.as_mut(), env, mock_info(ADDRESS2, &[]), ExecuteMsg::UpdateCompoundBounty { bps: 0 }).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    #[test]
+    fn pooled_stake_is_delegated_and_harvested() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &coins(1001, "orai")), ExecuteMsg::Stake {}).unwrap();
+
+        let validator = |address: &str| Validator {
+            address: address.to_string(),
+            commission: Decimal::percent(5),
+            max_commission: Decimal::percent(10),
+            max_change_rate: Decimal::percent(1),
+        };
+        let delegation = |validator: &str, amount: u128, rewards: u128| FullDelegation {
+            delegator: env.contract.address.clone(),
+            validator: validator.to_string(),
+            amount: coin(amount, "orai"),
+            can_redelegate: coin(amount, "orai"),
+            accumulated_rewards: coins(rewards, "orai"),
+        };
 
-        eprintln!("user2's balance: {}", res.reward);*/
+        // Only a stake token the chain bonds can be delegated, to known validators
+        let msg = ExecuteMsg::UpdateDelegation { validators: vec!["val1".to_string(), "val2".to_string()], unbonding_period: 1000, max_entries: None };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::CannotDelegate {}));
+        deps.querier.update_staking("orai", &[validator("val1")], &[]);
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::UnknownValidator { .. }));
+        deps.querier.update_staking("orai", &[validator("val1"), validator("val2")], &[]);
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // What is already staked is spread over the validators
+        let delegate = |validator: &str, amount: u128| CosmosMsg::Staking(StakingMsg::Delegate {
+            validator: validator.to_string(),
+            amount: coin(amount, "orai"),
+        });
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        assert_eq!(res.messages.iter().map(|msg| msg.msg.clone()).collect::<Vec<_>>(), vec![delegate("val1", 501), delegate("val2", 500)]);
+
+        // New stake follows
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &coins(999, "orai")), ExecuteMsg::Stake {}).unwrap();
+        assert_eq!(res.messages.iter().map(|msg| msg.msg.clone()).collect::<Vec<_>>(), vec![delegate("val1", 500), delegate("val2", 499)]);
+
+        // Staking rewards are withdrawn and split by stake on top of the cw20 incentive
+        deps.querier.update_staking("orai", &[validator("val1"), validator("val2")], &[delegation("val1", 1001, 30), delegation("val2", 999, 0)]);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::Harvest {}).unwrap();
+        assert_eq!(res.messages[0].msg, CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward { validator: "val1".to_string() }));
+        assert_eq!(res.messages.len(), 1);
+
+        let msg = QueryMsg::ViewReward { account: ADDRESS2.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.rewards.len(), 2);
+        assert!(res.rewards.contains(&RewardAmount { denom: Denom::Native("orai".to_string()), amount: Uint128::new(15) }));
+
+        deps.querier.update_staking("orai", &[validator("val1"), validator("val2")], &[delegation("val1", 1001, 0), delegation("val2", 999, 0)]);
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::Harvest {}).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToHarvest {}));
+
+        // Unstaking undelegates from the largest delegation first and waits for the chain
+        env.block.time = env.block.time.plus_seconds(10);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::Unstake { amount: 1001, early_exit: None }).unwrap();
+        assert_eq!(res.messages.iter().map(|msg| msg.msg.clone()).collect::<Vec<_>>(), vec![
+            CosmosMsg::Staking(StakingMsg::Undelegate { validator: "val1".to_string(), amount: coin(1001, "orai") }),
+        ]);
+        let msg = QueryMsg::PendingUnbondings { address: ADDRESS2.to_string() };
+        let res: PendingUnbondingsResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.unbondings[0].release_at, env.block.time.plus_seconds(1000));
+
+        // Later unstakes wait for the next batch, one epoch of 1000 / 7 + 1 seconds on
+        deps.querier.update_staking("orai", &[validator("val1"), validator("val2")], &[delegation("val2", 999, 0)]);
+        let sent_at = env.block.time;
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), ExecuteMsg::Unstake { amount: 99, early_exit: None }).unwrap();
+        assert!(res.messages.is_empty());
+        let msg = QueryMsg::PendingUnbondings { address: ADDRESS1.to_string() };
+        let res: PendingUnbondingsResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.unbondings[0].release_at, sent_at.plus_seconds(143 + 1000));
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::Harvest {}).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToHarvest {}));
+
+        // Anyone sends it once the epoch is over
+        env.block.time = sent_at.plus_seconds(143);
+        let res = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::Harvest {}).unwrap();
+        assert_eq!(res.messages.iter().map(|msg| msg.msg.clone()).collect::<Vec<_>>(), vec![
+            CosmosMsg::Staking(StakingMsg::Undelegate { validator: "val2".to_string(), amount: coin(99, "orai") }),
+        ]);
+
+        // Validators leaving the set hand their delegations to the remaining ones
+        deps.querier.update_staking("orai", &[validator("val1"), validator("val2")], &[delegation("val2", 900, 0)]);
+        let msg = ExecuteMsg::UpdateDelegation { validators: vec!["val1".to_string()], unbonding_period: 1000, max_entries: None };
+        let res = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+        assert_eq!(res.messages.iter().map(|msg| msg.msg.clone()).collect::<Vec<_>>(), vec![
+            CosmosMsg::Staking(StakingMsg::Redelegate { src_validator: "val2".to_string(), dst_validator: "val1".to_string(), amount: coin(900, "orai") }),
+        ]);
+
+        // Once per epoch only
+        deps.querier.update_staking("orai", &[validator("val1"), validator("val2")], &[delegation("val1", 900, 0)]);
+        let msg = ExecuteMsg::UpdateDelegation { validators: vec!["val2".to_string()], unbonding_period: 1000, max_entries: None };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::RedelegationTooSoon { .. }));
+        env.block.time = env.block.time.plus_seconds(143);
+        execute(deps.as_mut(), env, mock_info(ADDRESS1, &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn two_step_role_transfer() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+
+        // Only the admin proposes
+        let msg = ExecuteMsg::ProposeNewAdmin { address: ADDRESS2.to_string(), expiry: None };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        // Only the proposed address accepts
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::AcceptAdmin {}).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::AcceptAdmin {}).unwrap();
+
+        let res: ConfigResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(res.admin, Addr::unchecked(ADDRESS2));
+        assert_eq!(res.pending_admin, None);
+
+        // Expired proposals can't be accepted
+        let expiry = Expiration::AtHeight(env.block.height + 10);
+        let msg = ExecuteMsg::ProposeNewUpdater { address: LP_TOKEN.to_string(), expiry: Some(expiry) };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), msg).unwrap();
+
+        let res: ConfigResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(res.pending_updater, Some(PendingRole { address: Addr::unchecked(LP_TOKEN), expiry }));
+
+        env.block.height += 10;
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::AcceptUpdater {}).unwrap_err();
+        assert!(matches!(err, ContractError::ProposalExpired {}));
+
+        // Cancelled proposals are gone
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::CancelUpdaterProposal {}).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), ExecuteMsg::AcceptUpdater {}).unwrap_err();
+        assert!(matches!(err, ContractError::NoPendingProposal {}));
+
+        let res: ConfigResponse = from_json(query(deps.as_ref(), env, QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(res.updater, Addr::unchecked(ADDRESS2));
+        assert_eq!(res.pending_updater, None);
+    }
+
+    #[test]
+    fn pause_switches() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let env = mock_env();
+        let mut msg = default_instantiate_msg();
+        msg.guardian = Some(LP_TOKEN.to_string());
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), msg).unwrap();
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Stakers can't pause
+        let pause = ExecuteMsg::SetPause { stake: Some(true), unstake: None, claim: Some(true), oracle: None };
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), pause.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // The guardian pauses stake and claim only
+        execute(deps.as_mut(), env.clone(), mock_info(LP_TOKEN, &[]), pause).unwrap();
+        let res: PauseStateResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::PauseState {}).unwrap()).unwrap();
+        assert!(res.pause.stake && res.pause.claim && !res.pause.unstake && !res.pause.oracle);
+
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap_err();
+        assert!(matches!(err, ContractError::Paused { .. }));
+        let err = execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::ClaimReward { recipient: None }).unwrap_err();
+        assert!(matches!(err, ContractError::Paused { .. }));
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS2, &[]), ExecuteMsg::Unstake { amount: 50, early_exit: None }).unwrap();
+
+        // The admin lifts it
+        let unpause = ExecuteMsg::SetPause { stake: Some(false), unstake: None, claim: Some(false), oracle: None };
+        execute(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), unpause).unwrap();
+        let info = mock_info(ADDRESS2, &coins(100, "orai"));
+        execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap();
+    }
+
+    #[test]
+    fn all_stakers_paginates() {
+        let mut deps = mock_dependencies();
+        mock_cw20_tokens(&mut deps);
+        let mut env = mock_env();
+        instantiate(deps.as_mut(), env.clone(), mock_info(ADDRESS1, &[]), default_instantiate_msg()).unwrap();
+        fund_cw20(&mut deps, &env, USDC_ADDRESS, 10_000_000_000);
 
+        for (staker, amount) in [(ADDRESS2, 100), (ADDRESS1, 300), (LP_TOKEN, 600)] {
+            let info = mock_info(staker, &coins(amount, "orai"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        }
+        env.block.time = env.block.time.plus_seconds(10);
+
+        let msg = QueryMsg::AllStakers { start_after: None, limit: Some(2) };
+        let page: AllStakersResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        let addresses: Vec<_> = page.stakers.iter().map(|staker| staker.address.to_string()).collect();
+        assert_eq!(addresses, vec![ADDRESS2, LP_TOKEN]);
+
+        let msg = QueryMsg::AllStakers { start_after: Some(LP_TOKEN.to_string()), limit: None };
+        let rest: AllStakersResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(rest.stakers.len(), 1);
+        assert_eq!(rest.stakers[0].address, Addr::unchecked(ADDRESS1));
+        assert_eq!(rest.stakers[0].balance, Uint128::new(300));
+
+        // Pending rewards match ViewReward
+        let msg = QueryMsg::ViewReward { account: ADDRESS1.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(rest.stakers[0].rewards[0].pending, res.rewards[0].amount);
+        assert_eq!(rest.stakers[0].rewards[0].pending, Uint128::new(6969));
     }
-}
\ No newline at end of file
+}
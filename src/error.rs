@@ -1,4 +1,7 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{
+    CheckedFromRatioError, CheckedMultiplyFractionError, CheckedMultiplyRatioError, ConversionOverflowError,
+    Decimal, OverflowError, StdError,
+};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,15 +9,151 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("{0}")]
+    CheckedFromRatio(#[from] CheckedFromRatioError),
+
+    #[error("{0}")]
+    CheckedMultiplyRatio(#[from] CheckedMultiplyRatioError),
+
+    #[error("{0}")]
+    CheckedMultiplyFraction(#[from] CheckedMultiplyFractionError),
+
+    #[error("{0}")]
+    ConversionOverflow(#[from] ConversionOverflowError),
+
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+
+    #[error("Cannot migrate from a different contract: {previous_contract}")]
+    CannotMigrate{ previous_contract: String },
+
+    #[error("Cannot migrate from newer version {previous_version} to {new_version}")]
+    CannotDowngrade{ previous_version: String, new_version: String },
+
+    #[error("Migration requires {field}")]
+    MissingMigrationField{ field: String },
+
     #[error("Unauthorized")]
     Unauthorized {},
 
-    #[error("Only ORAI is acceptable")]
+    #[error("TWAP window of {window_seconds}s is empty or not covered by price observations")]
+    TwapWindowNotCovered{ window_seconds: u64 },
+
+    #[error("Price moves more than the allowed {max_deviation} in one update")]
+    PriceDeviationTooLarge{ max_deviation: Decimal },
+
+    #[error("A max price deviation can't be set and cleared at once")]
+    ConflictingMaxPriceDeviation{},
+
+    #[error("{operation} is paused")]
+    Paused{ operation: String },
+
+    #[error("No role transfer is pending")]
+    NoPendingProposal{},
+
+    #[error("Role transfer proposal has expired")]
+    ProposalExpired{},
+
+    #[error("Sent funds do not match the stake denom")]
     WrongNativeToken{},
 
+    #[error("Cw20 token is not accepted for this operation")]
+    WrongCw20Token{},
+
+    #[error("Funding amount must be positive")]
+    WrongFundingAmount{},
+
     #[error("Staked amount must be positive")]
     WrongStakeAmount{},
 
     #[error("Not enough balance")]
     NotEnoughBalance{},
-}
\ No newline at end of file
+
+    #[error("No unbonded funds ready to withdraw")]
+    NothingToWithdraw{},
+
+    #[error("Stake is locked until its lock positions expire")]
+    StakeLocked{},
+
+    #[error("Early exit penalty can't exceed 10000 bps")]
+    InvalidEarlyExitPenalty{},
+
+    #[error("Compound bounty can't exceed {max_bps} bps")]
+    InvalidCompoundBounty{ max_bps: u16 },
+
+    #[error("The stake token isn't a reward asset")]
+    StakeNotRewarded{},
+
+    #[error("No rewards to compound")]
+    NothingToCompound{},
+
+    #[error("Only a native stake token matching the bonded denom can be delegated")]
+    CannotDelegate{},
+
+    #[error("Validator set must be non-empty without duplicates")]
+    InvalidValidators{},
+
+    #[error("Unknown validator {validator}")]
+    UnknownValidator{ validator: String },
+
+    #[error("No staking rewards to harvest")]
+    NothingToHarvest{},
+
+    #[error("Unbonding entries per validator must be positive")]
+    InvalidMaxEntries{},
+
+    #[error("Delegations can't move between validators again before {next_at}")]
+    RedelegationTooSoon{ next_at: u64 },
+
+    #[error("Lock tiers can't be combined with a receipt token")]
+    ReceiptTokenWithLocks{},
+
+    #[error("Positions aren't transferable without a receipt token")]
+    NoReceiptToken{},
+
+    #[error("Amount must be positive")]
+    InvalidZeroAmount{},
+
+    #[error("Hook {addr} is already registered")]
+    HookAlreadyRegistered{ addr: String },
+
+    #[error("Hook {addr} is not registered")]
+    HookNotRegistered{ addr: String },
+
+    #[error("No expired lock positions to release")]
+    NothingToUnlock{},
+
+    #[error("No lock tier for a duration of {duration}s")]
+    UnknownLockTier{ duration: u64 },
+
+    #[error("Lock tiers need distinct positive durations and multipliers of at least 1")]
+    InvalidLockTiers{},
+
+    #[error("Unknown reward asset: {asset}")]
+    UnknownRewardAsset{ asset: String },
+
+    #[error("Reward asset already registered: {asset}")]
+    DuplicateRewardAsset{ asset: String },
+
+    #[error("Reward schedule must be non-empty, in the future and after the queued schedules")]
+    InvalidRewardSchedule{},
+}
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
+}
+
+/// Lets queries share the execute helpers, which report arithmetic failures as `ContractError`
+impl From<ContractError> for StdError {
+    fn from(err: ContractError) -> Self {
+        match err {
+            ContractError::Std(err) => err,
+            err => StdError::generic_err(err.to_string()),
+        }
+    }
+}
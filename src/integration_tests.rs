@@ -0,0 +1,841 @@
+#[cfg(test)]
+mod tests {
+    use crate::helpers::CwTemplateContract;
+    use crate::msg::{ExecuteMsg, InstantiateMsg, OraclePriceResponse, OracleQueryMsg, QueryMsg, RewardAssetMsg, ViewAPRResponse};
+    use crate::state::RewardSchedule;
+    use cosmwasm_std::{
+        coins, to_json_binary, Addr, Binary, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError,
+        StdResult, Uint128,
+    };
+    use cw20::UncheckedDenom;
+    use cosmwasm_std::testing::{MockApi, MockStorage};
+    use cw_multi_test::{App, AppBuilder, BankKeeper, Contract, ContractWrapper, Executor, FailingModule, WasmKeeper};
+    use cw_storage_plus::Item;
+
+    pub fn contract_template() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply);
+        Box::new(contract)
+    }
+
+    pub fn contract_receipt_token() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            och_receipt_token::contract::execute,
+            och_receipt_token::contract::instantiate,
+            och_receipt_token::contract::query,
+        );
+        Box::new(contract)
+    }
+
+    /// Oracle contract quoting a single price set by whoever calls it, or failing when unset
+    mod mock_oracle {
+        use super::*;
+
+        const PRICE: Item<Option<Decimal>> = Item::new("price");
+
+        pub fn instantiate(deps: DepsMut, _env: Env, _info: MessageInfo, price: Option<Decimal>) -> StdResult<Response> {
+            PRICE.save(deps.storage, &price)?;
+            Ok(Response::new())
+        }
+
+        pub fn execute(deps: DepsMut, _env: Env, _info: MessageInfo, price: Option<Decimal>) -> StdResult<Response> {
+            PRICE.save(deps.storage, &price)?;
+            Ok(Response::new())
+        }
+
+        pub fn query(deps: Deps, env: Env, msg: OracleQueryMsg) -> StdResult<Binary> {
+            let OracleQueryMsg::Price { base, quote } = msg;
+            assert_eq!((base.as_str(), quote.as_str()), (NATIVE_DENOM, REWARD_DENOM));
+
+            let price = PRICE.load(deps.storage)?.ok_or_else(|| StdError::not_found("price"))?;
+            to_json_binary(&OraclePriceResponse { price, updated_at: env.block.time })
+        }
+    }
+
+    pub fn contract_mock_oracle() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(mock_oracle::execute, mock_oracle::instantiate, mock_oracle::query);
+        Box::new(contract)
+    }
+
+    /// Contract keeping the last cw20 `Receive` hook it got
+    mod mock_receiver {
+        use super::*;
+        use cw20::Cw20ReceiveMsg;
+
+        pub const RECEIVED: Item<Cw20ReceiveMsg> = Item::new("received");
+
+        pub fn instantiate(_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty) -> StdResult<Response> {
+            Ok(Response::new())
+        }
+
+        pub fn execute(deps: DepsMut, _env: Env, _info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+            match msg {
+                ExecuteMsg::Receive(msg) => RECEIVED.save(deps.storage, &msg)?,
+                msg => return Err(StdError::generic_err(format!("unsupported {:?}", msg))),
+            }
+            Ok(Response::new())
+        }
+
+        pub fn query(deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+            to_json_binary(&RECEIVED.load(deps.storage)?)
+        }
+    }
+
+    pub fn contract_mock_receiver() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(mock_receiver::execute, mock_receiver::instantiate, mock_receiver::query);
+        Box::new(contract)
+    }
+
+    const USER: &str = "user";
+    const ADMIN: &str = "admin";
+    const NATIVE_DENOM: &str = "orai";
+    const REWARD_DENOM: &str = "uusdc";
+
+    fn mock_app() -> App {
+        AppBuilder::new().build(|router, _, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(USER), coins(1_000_000_000, NATIVE_DENOM))
+                .unwrap();
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(ADMIN), coins(1_000_000_000_000, REWARD_DENOM))
+                .unwrap();
+        })
+    }
+
+    /// Staking pool paying 2323 uusdc per second with a pushed price of 2.92, funded and staked into
+    fn proper_instantiate() -> (App, CwTemplateContract) {
+        let mut app = mock_app();
+        let cw_template_id = app.store_code(contract_template());
+
+        let start = app.block_info().time;
+        let msg = InstantiateMsg {
+            admin: None,
+            updater: None,
+            stake_denom: UncheckedDenom::Native(NATIVE_DENOM.to_string()),
+            rewards: vec![RewardAssetMsg {
+                denom: UncheckedDenom::Native(REWARD_DENOM.to_string()),
+                schedules: vec![RewardSchedule {
+                    start,
+                    end: start.plus_seconds(1_000_000),
+                    total_amount: Uint128::new(2_323_000_000),
+                }],
+                oracle: Some(Decimal::from_ratio(292u128, 100u128)),
+            }],
+            unbonding_period: None,
+            guardian: None,
+            price_feeder: None,
+            max_price_age: None,
+            max_price_deviation: None,
+            price_oracle: None,
+            lock_tiers: None,
+            early_exit_penalty: None,
+            compound_bounty_bps: None,
+            receipt_token: None,
+        };
+        let cw_template_contract_addr = app
+            .instantiate_contract(cw_template_id, Addr::unchecked(ADMIN), &msg, &[], "och-staking", None)
+            .unwrap();
+        let cw_template_contract = CwTemplateContract(cw_template_contract_addr);
+
+        let funds = coins(2_323_000_000, REWARD_DENOM);
+        app.execute_contract(Addr::unchecked(ADMIN), cw_template_contract.addr(), &ExecuteMsg::FundRewards {}, &funds)
+            .unwrap();
+
+        let funds = coins(1_000_000_000, NATIVE_DENOM);
+        app.execute_contract(Addr::unchecked(USER), cw_template_contract.addr(), &ExecuteMsg::Stake {}, &funds)
+            .unwrap();
+
+        (app, cw_template_contract)
+    }
+
+    fn query_apr(app: &App, contract: &CwTemplateContract) -> Option<Decimal> {
+        let res: ViewAPRResponse = app
+            .wrap()
+            .query_wasm_smart(contract.addr(), &QueryMsg::ViewAPR { deposit: None, twap_window: None })
+            .unwrap();
+        res.apr
+    }
+
+    /// Staking module holding delegated coins and paying undelegations back after
+    /// `UNBONDING_PERIOD`. Like the chain's end blocker, `end_block` pays out what matured
+    /// by the current block once its transactions ran. Staking rewards can't be withdrawn,
+    /// multi-test 0.15 has no pluggable distribution module.
+    mod mock_staking {
+        use super::*;
+
+        use anyhow::{bail, Result as AnyResult};
+        use cosmwasm_std::{
+            Api, BankMsg, BlockInfo, Coin, CustomQuery, FullDelegation, Querier, StakingMsg, StakingQuery,
+            Storage, Timestamp,
+        };
+        use cosmwasm_std::{
+            AllDelegationsResponse, AllValidatorsResponse, BondedDenomResponse, Delegation, DelegationResponse,
+            Validator, ValidatorResponse,
+        };
+        use cw_multi_test::{AppResponse, CosmosRouter, Module, Staking, StakingSudo};
+        use cw_storage_plus::Map;
+        use schemars::JsonSchema;
+        use serde::de::DeserializeOwned;
+
+        pub const VALIDATORS: [&str; 2] = ["validator", "validator2"];
+        pub const UNBONDING_PERIOD: u64 = 1000;
+        /// Unbonding entries per delegator and validator, redelegation entries per validator pair
+        const MAX_ENTRIES: usize = 7;
+        const MODULE: &str = "staking_module";
+
+        const DELEGATIONS: Map<(&Addr, &str), Uint128> = Map::new("mock_staking_delegations");
+        const UNBONDING_QUEUE: Item<Vec<(Timestamp, Addr, String, Uint128)>> = Item::new("mock_staking_unbonding_queue");
+        const REDELEGATIONS: Item<Vec<(Timestamp, Addr, String, String)>> = Item::new("mock_staking_redelegations");
+
+        pub struct MockStaking;
+
+        fn validator(address: &str) -> Validator {
+            Validator {
+                address: address.to_string(),
+                commission: Decimal::zero(),
+                max_commission: Decimal::one(),
+                max_change_rate: Decimal::one(),
+            }
+        }
+
+        fn undelegate(storage: &mut dyn Storage, delegator: &Addr, validator: &str, amount: Uint128) -> AnyResult<()> {
+            let delegated = DELEGATIONS.may_load(storage, (delegator, validator))?.unwrap_or_default();
+            if delegated < amount {
+                bail!("undelegating more than delegated");
+            }
+            DELEGATIONS.save(storage, (delegator, validator), &(delegated - amount))?;
+            Ok(())
+        }
+
+        impl Module for MockStaking {
+            type ExecT = StakingMsg;
+            type QueryT = StakingQuery;
+            type SudoT = StakingSudo;
+
+            fn execute<ExecC, QueryC>(
+                &self,
+                api: &dyn Api,
+                storage: &mut dyn Storage,
+                router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+                block: &BlockInfo,
+                sender: Addr,
+                msg: StakingMsg,
+            ) -> AnyResult<AppResponse>
+            where
+                ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+                QueryC: CustomQuery + DeserializeOwned + 'static,
+            {
+                match msg {
+                    StakingMsg::Delegate { validator, amount } => {
+                        if !VALIDATORS.contains(&validator.as_str()) {
+                            bail!("unknown validator {}", validator);
+                        }
+                        let send = BankMsg::Send { to_address: MODULE.to_string(), amount: vec![amount.clone()] };
+                        router.execute(api, storage, block, sender.clone(), send.into())?;
+                        DELEGATIONS.update(storage, (&sender, &validator), |delegated| -> StdResult<_> {
+                            Ok(delegated.unwrap_or_default() + amount.amount)
+                        })?;
+                    }
+                    StakingMsg::Undelegate { validator, amount } => {
+                        undelegate(storage, &sender, &validator, amount.amount)?;
+                        let mut queue = UNBONDING_QUEUE.may_load(storage)?.unwrap_or_default();
+                        let entries = queue.iter().filter(|(_, delegator, entry_validator, _)| (delegator, entry_validator) == (&sender, &validator)).count();
+                        if entries >= MAX_ENTRIES {
+                            bail!("too many unbonding delegation entries for (delegator, validator) tuple");
+                        }
+                        queue.push((block.time.plus_seconds(UNBONDING_PERIOD), sender, validator, amount.amount));
+                        UNBONDING_QUEUE.save(storage, &queue)?;
+                    }
+                    StakingMsg::Redelegate { src_validator, dst_validator, amount } => {
+                        if !VALIDATORS.contains(&dst_validator.as_str()) {
+                            bail!("unknown validator {}", dst_validator);
+                        }
+                        let mut redelegations = REDELEGATIONS.may_load(storage)?.unwrap_or_default();
+                        redelegations.retain(|(completion, _, _, _)| *completion > block.time);
+                        let entry = (sender.clone(), src_validator.clone(), dst_validator.clone());
+                        let entries = redelegations.iter().filter(|(_, delegator, src, dst)| (delegator, src, dst) == (&entry.0, &entry.1, &entry.2)).count();
+                        if entries >= MAX_ENTRIES {
+                            bail!("too many redelegation entries for (delegator, src-validator, dst-validator) tuple");
+                        }
+                        redelegations.push((block.time.plus_seconds(UNBONDING_PERIOD), entry.0, entry.1, entry.2));
+                        REDELEGATIONS.save(storage, &redelegations)?;
+                        undelegate(storage, &sender, &src_validator, amount.amount)?;
+                        DELEGATIONS.update(storage, (&sender, &dst_validator), |delegated| -> StdResult<_> {
+                            Ok(delegated.unwrap_or_default() + amount.amount)
+                        })?;
+                    }
+                    msg => bail!("unsupported staking message {:?}", msg),
+                }
+                Ok(AppResponse::default())
+            }
+
+            fn sudo<ExecC, QueryC>(
+                &self,
+                _api: &dyn Api,
+                _storage: &mut dyn Storage,
+                _router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+                _block: &BlockInfo,
+                msg: StakingSudo,
+            ) -> AnyResult<AppResponse>
+            where
+                ExecC: std::fmt::Debug + Clone + PartialEq + JsonSchema + DeserializeOwned + 'static,
+                QueryC: CustomQuery + DeserializeOwned + 'static,
+            {
+                bail!("unsupported staking sudo {:?}", msg)
+            }
+
+            fn query(
+                &self,
+                _api: &dyn Api,
+                storage: &dyn Storage,
+                _querier: &dyn Querier,
+                _block: &BlockInfo,
+                request: StakingQuery,
+            ) -> AnyResult<Binary> {
+                let delegation = |delegator: &Addr, validator: &str| -> StdResult<Option<FullDelegation>> {
+                    let amount = DELEGATIONS.may_load(storage, (delegator, validator))?.unwrap_or_default();
+                    Ok((!amount.is_zero()).then(|| FullDelegation {
+                        delegator: delegator.clone(),
+                        validator: validator.to_string(),
+                        amount: Coin { denom: NATIVE_DENOM.to_string(), amount },
+                        can_redelegate: Coin { denom: NATIVE_DENOM.to_string(), amount },
+                        accumulated_rewards: vec![],
+                    }))
+                };
+                let res = match request {
+                    StakingQuery::BondedDenom {} => to_json_binary(&BondedDenomResponse { denom: NATIVE_DENOM.to_string() })?,
+                    StakingQuery::AllValidators {} => {
+                        to_json_binary(&AllValidatorsResponse { validators: VALIDATORS.iter().map(|address| validator(address)).collect() })?
+                    }
+                    StakingQuery::Validator { address } => {
+                        let validator = VALIDATORS.contains(&address.as_str()).then(|| validator(&address));
+                        to_json_binary(&ValidatorResponse { validator })?
+                    }
+                    StakingQuery::AllDelegations { delegator } => {
+                        let delegator = Addr::unchecked(delegator);
+                        let mut delegations = vec![];
+                        for validator in VALIDATORS {
+                            delegations.extend(delegation(&delegator, validator)?.map(Delegation::from));
+                        }
+                        to_json_binary(&AllDelegationsResponse { delegations })?
+                    }
+                    StakingQuery::Delegation { delegator, validator } => {
+                        let delegation = delegation(&Addr::unchecked(delegator), &validator)?;
+                        to_json_binary(&DelegationResponse { delegation })?
+                    }
+                    request => bail!("unsupported staking query {:?}", request),
+                };
+                Ok(res)
+            }
+        }
+
+        impl Staking for MockStaking {}
+
+        /// Pays the undelegations matured by the current block back to their delegators
+        pub fn end_block(app: &mut StakingApp) {
+            let block = app.block_info();
+            app.init_modules(|router, api, storage| -> AnyResult<()> {
+                let queue = UNBONDING_QUEUE.may_load(storage)?.unwrap_or_default();
+                let (matured, pending): (Vec<_>, Vec<_>) = queue.into_iter().partition(|(release_at, _, _, _)| *release_at <= block.time);
+                UNBONDING_QUEUE.save(storage, &pending)?;
+                for (_, delegator, _, amount) in matured {
+                    let send = BankMsg::Send { to_address: delegator.to_string(), amount: coins(amount.u128(), NATIVE_DENOM) };
+                    router.execute(api, storage, &block, Addr::unchecked(MODULE), send.into())?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        pub fn delegated(app: &StakingApp, delegator: &Addr) -> Uint128 {
+            app.wrap()
+                .query_all_delegations(delegator)
+                .unwrap()
+                .iter()
+                .map(|delegation| delegation.amount.amount)
+                .sum()
+        }
+
+        pub fn delegated_to(app: &StakingApp, delegator: &Addr, validator: &str) -> Uint128 {
+            app.wrap()
+                .query_delegation(delegator, validator)
+                .unwrap()
+                .map(|delegation| delegation.amount.amount)
+                .unwrap_or_default()
+        }
+    }
+
+    type StakingApp = App<BankKeeper, MockApi, MockStorage, FailingModule<Empty, Empty, Empty>, WasmKeeper<Empty, Empty>, mock_staking::MockStaking>;
+
+    mod delegation {
+        use super::*;
+        use crate::msg::{EarlyExitPenaltyMsg, ViewRewardResponse};
+        use crate::state::LockTier;
+        use mock_staking::{delegated, delegated_to, end_block, MockStaking, UNBONDING_PERIOD, VALIDATORS};
+
+        fn staking_app() -> StakingApp {
+            AppBuilder::new().with_staking(MockStaking).build(|router, _, storage| {
+                router
+                    .bank
+                    .init_balance(storage, &Addr::unchecked(USER), coins(1_000_000, NATIVE_DENOM))
+                    .unwrap();
+                router
+                    .bank
+                    .init_balance(storage, &Addr::unchecked(ADMIN), coins(1_000_000, NATIVE_DENOM))
+                    .unwrap();
+            })
+        }
+
+        fn instantiate_msg() -> InstantiateMsg {
+            InstantiateMsg {
+                admin: None,
+                updater: None,
+                stake_denom: UncheckedDenom::Native(NATIVE_DENOM.to_string()),
+                rewards: vec![],
+                unbonding_period: Some(100),
+                guardian: None,
+                price_feeder: None,
+                max_price_age: None,
+                max_price_deviation: None,
+                price_oracle: None,
+                lock_tiers: None,
+                early_exit_penalty: None,
+                compound_bounty_bps: None,
+                receipt_token: None,
+            }
+        }
+
+        #[test]
+        fn stake_is_delegated_until_the_chain_unbonds_it() {
+            let mut app = staking_app();
+            let code_id = app.store_code(contract_template());
+            let contract = app
+                .instantiate_contract(code_id, Addr::unchecked(ADMIN), &instantiate_msg(), &[], "och-staking", None)
+                .unwrap();
+
+            // Stake held before delegation is turned on gets delegated along with new stake
+            let funds = coins(400_000, NATIVE_DENOM);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &funds).unwrap();
+            let msg = ExecuteMsg::UpdateDelegation { validators: vec![VALIDATORS[0].to_string()], unbonding_period: UNBONDING_PERIOD, max_entries: None };
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+            let funds = coins(600_000, NATIVE_DENOM);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &funds).unwrap();
+
+            assert_eq!(delegated(&app, &contract), Uint128::new(1_000_000));
+            assert_eq!(app.wrap().query_balance(&contract, NATIVE_DENOM).unwrap().amount, Uint128::zero());
+
+            // The contract's shorter unbonding period gives way to the chain's
+            let msg = ExecuteMsg::Unstake { amount: 300_000, early_exit: None };
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &msg, &[]).unwrap();
+            assert_eq!(delegated(&app, &contract), Uint128::new(700_000));
+
+            app.update_block(|block| block.time = block.time.plus_seconds(100));
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::WithdrawUnbonded {}, &[])
+                .unwrap_err();
+
+            // Once the chain released the coins they can be withdrawn
+            app.update_block(|block| block.time = block.time.plus_seconds(UNBONDING_PERIOD - 100));
+            end_block(&mut app);
+            app.update_block(|block| block.time = block.time.plus_seconds(5));
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::WithdrawUnbonded {}, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(USER, NATIVE_DENOM).unwrap().amount, Uint128::new(300_000));
+
+            // Validators leaving the set hand their delegations over
+            let msg = ExecuteMsg::UpdateDelegation { validators: vec![VALIDATORS[1].to_string()], unbonding_period: UNBONDING_PERIOD, max_entries: None };
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+            assert_eq!(delegated_to(&app, &contract, VALIDATORS[0]), Uint128::zero());
+            assert_eq!(delegated_to(&app, &contract, VALIDATORS[1]), Uint128::new(700_000));
+        }
+
+        #[test]
+        fn unstakes_are_batched_within_the_chain_entry_limit() {
+            let mut app = staking_app();
+            let code_id = app.store_code(contract_template());
+            let contract = app
+                .instantiate_contract(code_id, Addr::unchecked(ADMIN), &instantiate_msg(), &[], "och-staking", None)
+                .unwrap();
+            let msg = ExecuteMsg::UpdateDelegation { validators: vec![VALIDATORS[0].to_string()], unbonding_period: UNBONDING_PERIOD, max_entries: None };
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+            let funds = coins(200_000, NATIVE_DENOM);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &funds).unwrap();
+
+            // Twenty unstakes within one chain unbonding period share at most seven entries
+            for _ in 0..20 {
+                app.update_block(|block| block.time = block.time.plus_seconds(50));
+                end_block(&mut app);
+                let msg = ExecuteMsg::Unstake { amount: 5_000, early_exit: None };
+                app.execute_contract(Addr::unchecked(USER), contract.clone(), &msg, &[]).unwrap();
+            }
+            assert_eq!(delegated(&app, &contract), Uint128::new(105_000));
+
+            // The last batch goes out once its epoch is over
+            app.update_block(|block| block.time = block.time.plus_seconds(100));
+            end_block(&mut app);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Harvest {}, &[]).unwrap();
+            assert_eq!(delegated(&app, &contract), Uint128::new(100_000));
+
+            app.update_block(|block| block.time = block.time.plus_seconds(UNBONDING_PERIOD));
+            end_block(&mut app);
+            app.update_block(|block| block.time = block.time.plus_seconds(5));
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::WithdrawUnbonded {}, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(USER, NATIVE_DENOM).unwrap().amount, Uint128::new(900_000));
+            assert_eq!(app.wrap().query_balance(&contract, NATIVE_DENOM).unwrap().amount, Uint128::zero());
+        }
+
+        #[test]
+        fn undelegated_penalties_reach_stakers_once_released() {
+            let mut app = staking_app();
+            let code_id = app.store_code(contract_template());
+            let msg = InstantiateMsg {
+                lock_tiers: Some(vec![LockTier { duration: 10_000, multiplier: Decimal::one() }]),
+                early_exit_penalty: Some(EarlyExitPenaltyMsg { bps: 1000, treasury: None }),
+                ..instantiate_msg()
+            };
+            let contract = app
+                .instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], "och-staking", None)
+                .unwrap();
+            let msg = ExecuteMsg::UpdateDelegation { validators: vec![VALIDATORS[0].to_string()], unbonding_period: UNBONDING_PERIOD, max_entries: None };
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+
+            let funds = coins(100_000, NATIVE_DENOM);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::StakeLocked { duration: 10_000 }, &funds)
+                .unwrap();
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::Stake {}, &funds).unwrap();
+
+            // The 10% penalty unbonds with the rest and can't be claimed yet
+            let msg = ExecuteMsg::Unstake { amount: 100_000, early_exit: Some(true) };
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &msg, &[]).unwrap();
+            assert_eq!(delegated(&app, &contract), Uint128::new(100_000));
+            let pending_reward = |app: &StakingApp| -> Uint128 {
+                let res: ViewRewardResponse = app
+                    .wrap()
+                    .query_wasm_smart(&contract, &QueryMsg::ViewReward { account: ADMIN.to_string() })
+                    .unwrap();
+                res.rewards.iter().map(|reward| reward.amount).sum()
+            };
+            assert_eq!(pending_reward(&app), Uint128::zero());
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::Harvest {}, &[])
+                .unwrap_err();
+
+            // Released by the chain, the penalty goes to the stakers left
+            app.update_block(|block| block.time = block.time.plus_seconds(UNBONDING_PERIOD));
+            end_block(&mut app);
+            app.update_block(|block| block.time = block.time.plus_seconds(5));
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::Harvest {}, &[])
+                .unwrap();
+            assert_eq!(pending_reward(&app), Uint128::new(10_000));
+
+            let balance = app.wrap().query_balance(ADMIN, NATIVE_DENOM).unwrap().amount;
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::ClaimReward { recipient: None }, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(ADMIN, NATIVE_DENOM).unwrap().amount, balance + Uint128::new(10_000));
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::WithdrawUnbonded {}, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(USER, NATIVE_DENOM).unwrap().amount, Uint128::new(990_000));
+            assert_eq!(app.wrap().query_balance(&contract, NATIVE_DENOM).unwrap().amount, Uint128::zero());
+        }
+
+        #[test]
+        fn undelegated_penalties_reach_the_treasury_once_released() {
+            const TREASURY: &str = "treasury";
+            let mut app = staking_app();
+            let code_id = app.store_code(contract_template());
+            let msg = InstantiateMsg {
+                lock_tiers: Some(vec![LockTier { duration: 10_000, multiplier: Decimal::one() }]),
+                early_exit_penalty: Some(EarlyExitPenaltyMsg { bps: 1000, treasury: Some(TREASURY.to_string()) }),
+                ..instantiate_msg()
+            };
+            let contract = app
+                .instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], "och-staking", None)
+                .unwrap();
+            let msg = ExecuteMsg::UpdateDelegation { validators: vec![VALIDATORS[0].to_string()], unbonding_period: UNBONDING_PERIOD, max_entries: None };
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+
+            let funds = coins(100_000, NATIVE_DENOM);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::StakeLocked { duration: 10_000 }, &funds)
+                .unwrap();
+            let msg = ExecuteMsg::Unstake { amount: 100_000, early_exit: Some(true) };
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &msg, &[]).unwrap();
+
+            // The treasury never calls in, the first harvest after the release pays it
+            app.update_block(|block| block.time = block.time.plus_seconds(UNBONDING_PERIOD));
+            end_block(&mut app);
+            app.update_block(|block| block.time = block.time.plus_seconds(5));
+            assert_eq!(app.wrap().query_balance(TREASURY, NATIVE_DENOM).unwrap().amount, Uint128::zero());
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::Harvest {}, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(TREASURY, NATIVE_DENOM).unwrap().amount, Uint128::new(10_000));
+
+            // Paid once only
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::Harvest {}, &[])
+                .unwrap_err();
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::WithdrawUnbonded {}, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(&contract, NATIVE_DENOM).unwrap().amount, Uint128::zero());
+        }
+    }
+
+    mod receipt_token {
+        use super::*;
+        use crate::msg::{ConfigResponse, ReceiptTokenMsg, ReceiveMsg, ViewRewardResponse, ViewStakeAmount};
+        use cw20::{AllowanceResponse, BalanceResponse, Cw20ExecuteMsg, Cw20QueryMsg, Cw20ReceiveMsg, MinterResponse, TokenInfoResponse};
+
+        const HOLDER: &str = "holder";
+
+        fn instantiate_msg(rewards: Vec<RewardAssetMsg>, receipt_token: Option<ReceiptTokenMsg>) -> InstantiateMsg {
+            InstantiateMsg {
+                admin: None,
+                updater: None,
+                stake_denom: UncheckedDenom::Native(NATIVE_DENOM.to_string()),
+                rewards,
+                unbonding_period: None,
+                guardian: None,
+                price_feeder: None,
+                max_price_age: None,
+                max_price_deviation: None,
+                price_oracle: None,
+                lock_tiers: None,
+                early_exit_penalty: None,
+                compound_bounty_bps: None,
+                receipt_token,
+            }
+        }
+
+        /// Staking contract along with the receipt token it instantiated
+        fn instantiate_with_receipts(app: &mut App, rewards: Vec<RewardAssetMsg>) -> (Addr, Addr) {
+            let code_id = app.store_code(contract_template());
+            let receipt_code_id = app.store_code(contract_receipt_token());
+            let receipt_token = ReceiptTokenMsg {
+                code_id: receipt_code_id,
+                name: "Staked ORAI".to_string(),
+                symbol: "SORAI".to_string(),
+            };
+            let contract = app
+                .instantiate_contract(code_id, Addr::unchecked(ADMIN), &instantiate_msg(rewards, Some(receipt_token)), &[], "och-staking", None)
+                .unwrap();
+            let config: ConfigResponse = app.wrap().query_wasm_smart(&contract, &QueryMsg::Config {}).unwrap();
+            (contract, config.receipt_token.unwrap())
+        }
+
+        fn receipts(app: &App, token: &Addr, address: &str) -> Uint128 {
+            let res: BalanceResponse = app
+                .wrap()
+                .query_wasm_smart(token, &Cw20QueryMsg::Balance { address: address.to_string() })
+                .unwrap();
+            res.balance
+        }
+
+        fn total_supply(app: &App, token: &Addr) -> Uint128 {
+            let res: TokenInfoResponse = app.wrap().query_wasm_smart(token, &Cw20QueryMsg::TokenInfo {}).unwrap();
+            res.total_supply
+        }
+
+        fn stake(app: &App, contract: &Addr, address: &str) -> Uint128 {
+            let res: ViewStakeAmount = app
+                .wrap()
+                .query_wasm_smart(contract, &QueryMsg::CheckStakeAmount { address: address.to_string() })
+                .unwrap();
+            res.balance
+        }
+
+        fn pending_reward(app: &App, contract: &Addr, address: &str) -> Uint128 {
+            let res: ViewRewardResponse = app
+                .wrap()
+                .query_wasm_smart(contract, &QueryMsg::ViewReward { account: address.to_string() })
+                .unwrap();
+            res.rewards[0].amount
+        }
+
+        #[test]
+        fn rewards_follow_receipts_between_holders() {
+            let mut app = mock_app();
+            let start = app.block_info().time;
+            let rewards = vec![RewardAssetMsg {
+                denom: UncheckedDenom::Native(REWARD_DENOM.to_string()),
+                schedules: vec![RewardSchedule {
+                    start,
+                    end: start.plus_seconds(1_000_000),
+                    total_amount: Uint128::new(10_000_000),
+                }],
+                oracle: None,
+            }];
+            let (contract, token) = instantiate_with_receipts(&mut app, rewards);
+            let funds = coins(10_000_000, REWARD_DENOM);
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &ExecuteMsg::FundRewards {}, &funds)
+                .unwrap();
+
+            // The staking contract owns the receipt token, stake mints it 1:1
+            let minter: MinterResponse = app.wrap().query_wasm_smart(&token, &Cw20QueryMsg::Minter {}).unwrap();
+            assert_eq!(minter.minter, contract.to_string());
+            let funds = coins(1000, NATIVE_DENOM);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &funds).unwrap();
+            let info: TokenInfoResponse = app.wrap().query_wasm_smart(&token, &Cw20QueryMsg::TokenInfo {}).unwrap();
+            assert_eq!((info.symbol.as_str(), info.decimals, info.total_supply), ("SORAI", 6, Uint128::new(1000)));
+            assert_eq!(receipts(&app, &token, USER), Uint128::new(1000));
+
+            // Nobody but the staking contract mints
+            let mint = Cw20ExecuteMsg::Mint { recipient: USER.to_string(), amount: Uint128::new(1) };
+            app.execute_contract(Addr::unchecked(USER), token.clone(), &mint, &[]).unwrap_err();
+
+            // Transfers settle both sides on the spot, the recipient acting alone sees the right totals
+            app.update_block(|block| block.time = block.time.plus_seconds(100));
+            let transfer = Cw20ExecuteMsg::Transfer { recipient: HOLDER.to_string(), amount: Uint128::new(400) };
+            app.execute_contract(Addr::unchecked(USER), token.clone(), &transfer, &[]).unwrap();
+            app.update_block(|block| block.time = block.time.plus_seconds(100));
+            app.execute_contract(Addr::unchecked(HOLDER), contract.clone(), &ExecuteMsg::ClaimReward { recipient: None }, &[])
+                .unwrap();
+            assert_eq!(app.wrap().query_balance(HOLDER, REWARD_DENOM).unwrap().amount, Uint128::new(400));
+            assert_eq!(pending_reward(&app, &contract, USER), Uint128::new(1600));
+            assert_eq!(stake(&app, &contract, HOLDER), receipts(&app, &token, HOLDER));
+            assert_eq!(total_supply(&app, &token), Uint128::new(1000));
+
+            // Spenders move receipts within their allowance only
+            let allow = Cw20ExecuteMsg::IncreaseAllowance { spender: ADMIN.to_string(), amount: Uint128::new(100), expires: None };
+            app.execute_contract(Addr::unchecked(USER), token.clone(), &allow, &[]).unwrap();
+            let transfer_from = |amount: u128| Cw20ExecuteMsg::TransferFrom {
+                owner: USER.to_string(),
+                recipient: HOLDER.to_string(),
+                amount: Uint128::new(amount),
+            };
+            app.execute_contract(Addr::unchecked(ADMIN), token.clone(), &transfer_from(150), &[]).unwrap_err();
+            app.execute_contract(Addr::unchecked(ADMIN), token.clone(), &transfer_from(100), &[]).unwrap();
+            let msg = Cw20QueryMsg::Allowance { owner: USER.to_string(), spender: ADMIN.to_string() };
+            let res: AllowanceResponse = app.wrap().query_wasm_smart(&token, &msg).unwrap();
+            assert_eq!(res.allowance, Uint128::zero());
+
+            // Receipts only come back to the staking contract to be burnt
+            app.update_block(|block| block.time = block.time.plus_seconds(100));
+            let transfer = Cw20ExecuteMsg::Transfer { recipient: contract.to_string(), amount: Uint128::new(500) };
+            app.execute_contract(Addr::unchecked(HOLDER), token.clone(), &transfer, &[]).unwrap_err();
+            let unstake = Cw20ExecuteMsg::Send {
+                contract: contract.to_string(),
+                amount: Uint128::new(500),
+                msg: to_json_binary(&ReceiveMsg::Unstake {}).unwrap(),
+            };
+            app.execute_contract(Addr::unchecked(HOLDER), token.clone(), &unstake, &[]).unwrap();
+            assert_eq!(receipts(&app, &token, HOLDER), Uint128::zero());
+            assert_eq!(receipts(&app, &token, contract.as_str()), Uint128::zero());
+            assert_eq!(stake(&app, &contract, HOLDER), Uint128::zero());
+            assert_eq!(total_supply(&app, &token), Uint128::new(500));
+
+            // Only the remaining 500 earns
+            app.update_block(|block| block.time = block.time.plus_seconds(100));
+            assert_eq!(pending_reward(&app, &contract, USER), Uint128::new(3100));
+            assert_eq!(pending_reward(&app, &contract, HOLDER), Uint128::new(500));
+
+            // Unstaking directly burns out of the allowance given to the staking contract
+            let unstake = ExecuteMsg::Unstake { amount: 200, early_exit: None };
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &unstake, &[]).unwrap_err();
+            let allow = Cw20ExecuteMsg::IncreaseAllowance { spender: contract.to_string(), amount: Uint128::new(200), expires: None };
+            app.execute_contract(Addr::unchecked(USER), token.clone(), &allow, &[]).unwrap();
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &unstake, &[]).unwrap();
+            assert_eq!(receipts(&app, &token, USER), Uint128::new(300));
+            assert_eq!(stake(&app, &contract, USER), Uint128::new(300));
+            assert_eq!(total_supply(&app, &token), Uint128::new(300));
+        }
+
+        #[test]
+        fn receipts_are_sent_to_contracts() {
+            let mut app = mock_app();
+            let receiver_id = app.store_code(contract_mock_receiver());
+            let receiver = app
+                .instantiate_contract(receiver_id, Addr::unchecked(ADMIN), &Empty {}, &[], "receiver", None)
+                .unwrap();
+
+            // Positions aren't transferable without a receipt token
+            let code_id = app.store_code(contract_template());
+            let contract = app
+                .instantiate_contract(code_id, Addr::unchecked(ADMIN), &instantiate_msg(vec![], None), &[], "och-staking", None)
+                .unwrap();
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &coins(1000, NATIVE_DENOM))
+                .unwrap();
+            let msg = ExecuteMsg::ReceiptTransfer { owner: USER.to_string(), recipient: receiver.to_string(), amount: Uint128::new(1000) };
+            app.execute_contract(Addr::unchecked(USER), contract, &msg, &[]).unwrap_err();
+
+            let (contract, token) = instantiate_with_receipts(&mut app, vec![]);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &coins(1000, NATIVE_DENOM))
+                .unwrap();
+
+            // The receiving contract holds the position and hears about it
+            let send = Cw20ExecuteMsg::Send {
+                contract: receiver.to_string(),
+                amount: Uint128::new(1000),
+                msg: to_json_binary("hello").unwrap(),
+            };
+            app.execute_contract(Addr::unchecked(USER), token.clone(), &send, &[]).unwrap();
+            assert_eq!(receipts(&app, &token, receiver.as_str()), Uint128::new(1000));
+            assert_eq!(stake(&app, &contract, receiver.as_str()), Uint128::new(1000));
+            let received: Cw20ReceiveMsg = app.wrap().query_wasm_smart(&receiver, &Empty {}).unwrap();
+            assert_eq!(received, Cw20ReceiveMsg {
+                sender: USER.to_string(),
+                amount: Uint128::new(1000),
+                msg: to_json_binary("hello").unwrap(),
+            });
+        }
+
+        #[test]
+        fn receipts_stay_put_while_staking_is_paused() {
+            let mut app = mock_app();
+            let (contract, token) = instantiate_with_receipts(&mut app, vec![]);
+            app.execute_contract(Addr::unchecked(USER), contract.clone(), &ExecuteMsg::Stake {}, &coins(1000, NATIVE_DENOM))
+                .unwrap();
+            let transfer = Cw20ExecuteMsg::Transfer { recipient: HOLDER.to_string(), amount: Uint128::new(100) };
+
+            // Either switch stops receipts from moving, the token rolls back with the staking contract
+            for (pause_stake, pause_unstake) in [(Some(true), None), (Some(false), Some(true))] {
+                let msg = ExecuteMsg::SetPause { stake: pause_stake, unstake: pause_unstake, claim: None, oracle: None };
+                app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+                app.execute_contract(Addr::unchecked(USER), token.clone(), &transfer, &[]).unwrap_err();
+                assert_eq!(receipts(&app, &token, USER), Uint128::new(1000));
+                assert_eq!(stake(&app, &contract, HOLDER), Uint128::zero());
+            }
+
+            let msg = ExecuteMsg::SetPause { stake: None, unstake: Some(false), claim: None, oracle: None };
+            app.execute_contract(Addr::unchecked(ADMIN), contract.clone(), &msg, &[]).unwrap();
+            app.execute_contract(Addr::unchecked(USER), token.clone(), &transfer, &[]).unwrap();
+            assert_eq!(receipts(&app, &token, HOLDER), Uint128::new(100));
+            assert_eq!(stake(&app, &contract, HOLDER), Uint128::new(100));
+        }
+    }
+
+    mod price_oracle {
+        use super::*;
+
+        #[test]
+        fn oracle_contract_overrides_pushed_price() {
+            let (mut app, cw_template_contract) = proper_instantiate();
+
+            // 2323 uusdc/s over 1000 ORAI priced at 2.92 USDC
+            let pushed_apr = Decimal::from_ratio(250884u128, 10000u128);
+            assert_eq!(query_apr(&app, &cw_template_contract), Some(pushed_apr));
+
+            // Only the admin points the contract at an oracle
+            let oracle_id = app.store_code(contract_mock_oracle());
+            let price = Some(Decimal::from_ratio(584u128, 100u128));
+            let oracle = app
+                .instantiate_contract(oracle_id, Addr::unchecked(ADMIN), &price, &[], "oracle", None)
+                .unwrap();
+            let msg = ExecuteMsg::SetPriceOracle { price_oracle: Some(oracle.to_string()) };
+            app.execute_contract(Addr::unchecked(USER), cw_template_contract.addr(), &msg, &[])
+                .unwrap_err();
+            app.execute_contract(Addr::unchecked(ADMIN), cw_template_contract.addr(), &msg, &[])
+                .unwrap();
+
+            // Twice the price, half the APR
+            assert_eq!(query_apr(&app, &cw_template_contract), Some(Decimal::from_ratio(125442u128, 10000u128)));
+
+            // An oracle without a price falls back to the pushed one
+            let no_price: Option<Decimal> = None;
+            app.execute_contract(Addr::unchecked(ADMIN), oracle, &no_price, &[]).unwrap();
+            assert_eq!(query_apr(&app, &cw_template_contract), Some(pushed_apr));
+        }
+    }
+}
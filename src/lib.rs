@@ -1,6 +1,11 @@
 pub mod contract;
 mod error;
+pub mod helpers;
+pub mod integration_tests;
+mod migrations;
 pub mod msg;
+mod oracle;
+mod reward;
 pub mod state;
 
 pub use crate::error::ContractError;
\ No newline at end of file
@@ -0,0 +1,243 @@
+use cosmwasm_std::{DepsMut, Env, Order, StdResult, Uint128};
+
+use crate::error::ContractError;
+use crate::msg::MigrateMsg;
+
+/// 0.1.x kept a single reward stream with a perpetual rps, paid ORAI
+/// stakers out of a hardcoded USDC contract and stored the oracle price
+/// as a bare item.
+pub mod v0_1 {
+    use super::*;
+
+    use cosmwasm_std::{Addr, Decimal, Timestamp};
+    use cw20::UncheckedDenom;
+    use cw_storage_plus::{Item, Map};
+    use math::{Decimal256, Uint256};
+    use serde::{Deserialize, Serialize};
+
+    use crate::contract::DEFAULT_MAX_PRICE_AGE;
+    use crate::oracle::save_price;
+    use crate::state::{
+        asset_key, Config, PauseInfo, RewardInfo, StakeInfo, UserRewardInfo, CONFIG,
+        PAUSE, REWARDS, STAKED_BALANCES, TOTAL_STAKE, TOTAL_WEIGHT, USERS,
+    };
+
+    const PRECISION: Uint128 = Uint128::new(10u128.pow(6));
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct ConfigV0_1 {
+        admin: Addr,
+        updater: Addr,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct StakeInfoV0_1 {
+        balance: Uint128,
+        reward: Uint128,
+        index: Uint128,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct RewardInfoV0_1 {
+        last_update: Timestamp,
+        cur_sum_index: Uint128,
+        rps: Uint128,
+        total_stake: Uint128,
+    }
+
+    const CONFIG_V0_1: Item<ConfigV0_1> = Item::new("config");
+    const USERS_V0_1: Map<Addr, StakeInfoV0_1> = Map::new("users");
+    const REWARD_V0_1: Item<RewardInfoV0_1> = Item::new("reward");
+    const ORACLE_V0_1: Item<Uint128> = Item::new("oracle");
+
+    /// Rewrites the 0.1.x layout into the current one.
+    ///
+    /// The perpetual rps is accrued up to the migration block and then
+    /// stopped, the updater has to queue a funded schedule to resume
+    /// emission. Rewards already owed to stakers are booked as both funded
+    /// and distributed, assuming the contract holds them.
+    pub fn migrate(deps: DepsMut, env: &Env, msg: &MigrateMsg) -> Result<(), ContractError> {
+        let legacy_reward_denom = msg
+            .legacy_reward_denom
+            .clone()
+            .ok_or(ContractError::MissingMigrationField { field: "legacy_reward_denom".to_string() })?
+            .into_checked(deps.as_ref())?;
+        let stake_denom = msg
+            .stake_denom
+            .clone()
+            .unwrap_or_else(|| UncheckedDenom::Native("orai".to_string()))
+            .into_checked(deps.as_ref())?;
+
+        //Config
+        let legacy_config = CONFIG_V0_1.load(deps.storage)?;
+        let config = Config {
+            admin: legacy_config.admin.clone(),
+            updater: legacy_config.updater,
+            stake_denom,
+            unbonding_period: 0,
+            guardian: None,
+            price_feeder: legacy_config.admin,
+            max_price_age: DEFAULT_MAX_PRICE_AGE,
+            max_price_deviation: None,
+            price_oracle: None,
+            lock_tiers: vec![],
+            early_exit_penalty: None,
+            compound_bounty_bps: 0,
+            delegation: None,
+            receipt_token: None,
+        };
+        CONFIG.save(deps.storage, &config)?;
+        PAUSE.save(deps.storage, &PauseInfo::default())?;
+
+        //Accrue the old stream up to now
+        let mut legacy_reward = REWARD_V0_1.load(deps.storage)?;
+        let time_passed = env.block.time.seconds() - legacy_reward.last_update.seconds();
+        if legacy_reward.total_stake > Uint128::zero() && time_passed > 0 {
+            let reward = legacy_reward.rps * Uint128::from(time_passed);
+            legacy_reward.cur_sum_index += (reward * PRECISION) / legacy_reward.total_stake;
+        }
+
+        //Users, carrying their pending reward and index over to the legacy asset
+        //Their voting power starts at the migration block
+        let asset = asset_key(&legacy_reward_denom);
+        let legacy_users = USERS_V0_1
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+
+        let mut owed = Uint128::zero();
+        for (addr, legacy) in legacy_users {
+            owed += legacy.reward
+                + (legacy_reward.cur_sum_index - legacy.index) * legacy.balance / PRECISION;
+
+            let stake_info = StakeInfo {
+                balance: legacy.balance,
+                locks: vec![],
+                auto_compound: false,
+                rewards: vec![UserRewardInfo {
+                    asset: asset.clone(),
+                    reward: legacy.reward,
+                    index: Decimal256::from_ratio(Uint256::from(legacy.index), Uint256::from(PRECISION)),
+                }],
+            };
+            STAKED_BALANCES.save(deps.storage, addr.clone(), &legacy.balance, env.block.height)?;
+            USERS.save(deps.storage, addr, &stake_info)?;
+        }
+
+        //Reward stream && totals
+        let reward_info = RewardInfo {
+            denom: legacy_reward_denom,
+            last_update: env.block.time,
+            cur_sum_index: Decimal256::from_ratio(Uint256::from(legacy_reward.cur_sum_index), Uint256::from(PRECISION)),
+            schedules: vec![],
+            total_funded: owed,
+            total_distributed: owed,
+            total_claimed: Uint128::zero(),
+        };
+        REWARDS.save(deps.storage, &asset, &reward_info)?;
+        TOTAL_STAKE.save(deps.storage, &legacy_reward.total_stake, env.block.height)?;
+        TOTAL_WEIGHT.save(deps.storage, &legacy_reward.total_stake)?;
+        REWARD_V0_1.remove(deps.storage);
+
+        //Oracle
+        //0.1.x quoted the price with 6 decimals
+        if let Some(price) = ORACLE_V0_1.may_load(deps.storage)? {
+            save_price(deps.storage, &asset, Decimal::from_ratio(price, PRECISION), env.block.time)?;
+            ORACLE_V0_1.remove(deps.storage);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a single-staker 0.1.x state, as left by its instantiate and one stake
+    #[cfg(test)]
+    pub fn save_v0_1_state(storage: &mut dyn cosmwasm_std::Storage, last_update: Timestamp, staker: Addr) {
+        let config = ConfigV0_1 { admin: staker.clone(), updater: staker.clone() };
+        CONFIG_V0_1.save(storage, &config).unwrap();
+        let reward = RewardInfoV0_1 {
+            last_update,
+            cur_sum_index: Uint128::new(3_000_000),
+            rps: Uint128::new(10),
+            total_stake: Uint128::new(1000),
+        };
+        REWARD_V0_1.save(storage, &reward).unwrap();
+        let stake_info = StakeInfoV0_1 {
+            balance: Uint128::new(1000),
+            reward: Uint128::new(5),
+            index: Uint128::new(3_000_000),
+        };
+        USERS_V0_1.save(storage, staker, &stake_info).unwrap();
+        ORACLE_V0_1.save(storage, &Uint128::new(42)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_dependencies, mock_env};
+    use cosmwasm_std::{from_json, Addr, Decimal, Uint128};
+    use cw20::{Denom, UncheckedDenom};
+    use cw2::{get_contract_version, set_contract_version};
+
+    use super::v0_1::*;
+    use crate::contract::{migrate, query};
+    use crate::error::ContractError;
+    use crate::msg::{MigrateMsg, QueryMsg, ViewRewardResponse, VotingPowerAtHeightResponse};
+    use crate::state::{CONFIG, ORACLE, REWARDS, TOTAL_STAKE};
+
+    const STAKER: &str = "orai1kwyeufzwmwgqwy2aa7ycv4wxaglwd38lkepa05";
+
+    fn migrate_msg() -> MigrateMsg {
+        MigrateMsg {
+            unbonding_period: Some(86400),
+            stake_denom: None,
+            legacy_reward_denom: Some(UncheckedDenom::Native("uusdc".to_string())),
+        }
+    }
+
+    #[test]
+    fn migrates_v0_1_state() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        set_contract_version(deps.as_mut().storage, "crates.io:och-staking", "0.1.0").unwrap();
+        save_v0_1_state(deps.as_mut().storage, env.block.time, Addr::unchecked(STAKER));
+
+        env.block.time = env.block.time.plus_seconds(100);
+        migrate(deps.as_mut(), env.clone(), migrate_msg()).unwrap();
+
+        let version = get_contract_version(deps.as_ref().storage).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+        let config = CONFIG.load(deps.as_ref().storage).unwrap();
+        assert_eq!(config.stake_denom, Denom::Native("orai".to_string()));
+        assert_eq!(config.unbonding_period, 86400);
+        assert_eq!(TOTAL_STAKE.load(deps.as_ref().storage).unwrap(), Uint128::new(1000));
+        assert_eq!(ORACLE.load(deps.as_ref().storage, "uusdc").unwrap().price, Decimal::from_ratio(42u128, 1_000_000u128));
+
+        // 5 pending + 10 rps over 100 seconds, the perpetual rps stops at the migration
+        // Voting power starts at the migration block
+        let msg = QueryMsg::VotingPowerAtHeight { address: STAKER.to_string(), height: Some(env.block.height + 1) };
+        let res: VotingPowerAtHeightResponse = from_json(query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+        assert_eq!(res.power, Uint128::new(1000));
+
+        let reward = REWARDS.load(deps.as_ref().storage, "uusdc").unwrap();
+        assert!(reward.schedules.is_empty());
+        assert_eq!(reward.total_distributed, Uint128::new(1005));
+
+        env.block.time = env.block.time.plus_seconds(100);
+        let msg = QueryMsg::ViewReward { account: STAKER.to_string() };
+        let res: ViewRewardResponse = from_json(query(deps.as_ref(), env, msg).unwrap()).unwrap();
+        assert_eq!(res.rewards[0].amount, Uint128::new(1005));
+    }
+
+    #[test]
+    fn refuses_foreign_contracts_and_downgrades() {
+        let mut deps = mock_dependencies();
+
+        set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), migrate_msg()).unwrap_err();
+        assert!(matches!(err, ContractError::CannotMigrate { .. }));
+
+        set_contract_version(deps.as_mut().storage, "crates.io:och-staking", "99.0.0").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), migrate_msg()).unwrap_err();
+        assert!(matches!(err, ContractError::CannotDowngrade { .. }));
+    }
+}
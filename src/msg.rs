@@ -1,18 +1,79 @@
-use cosmwasm_std::Uint128;
+use cosmwasm_std::{Decimal, Timestamp, Uint128};
+use cosmwasm_std::Addr;
+use cw2::ContractVersion;
+use cw20::{Cw20ReceiveMsg, Denom, Expiration, UncheckedDenom};
+use math::Decimal256;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::state::{DelegationConfig, EarlyExitPenalty, LockPosition, LockTier, PauseInfo, PendingRole, RewardSchedule, UnbondingInfo};
+
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct InstantiateMsg {
     pub admin: Option<String>,
     pub updater: Option<String>,
-    pub rps: Option<u128>,
-    pub oracle: Option<u128>,
+    /// Asset users stake into the pool, e.g. `Native("orai")`
+    pub stake_denom: UncheckedDenom,
+    /// Reward streams paid out to stakers, e.g. USDC plus a partner token
+    pub rewards: Vec<RewardAssetMsg>,
+    /// Cooldown in seconds between `Unstake` and `WithdrawUnbonded`, defaults to 0
+    pub unbonding_period: Option<u64>,
+    /// Address besides the admin allowed to pause operations
+    pub guardian: Option<String>,
+    /// Address pushing oracle prices, defaults to the admin
+    pub price_feeder: Option<String>,
+    /// Seconds a price stays fresh, defaults to a day
+    pub max_price_age: Option<u64>,
+    /// Largest relative move of one price update, e.g. `0.2` for 20%
+    pub max_price_deviation: Option<Decimal>,
+    /// Oracle contract answering `OracleQueryMsg::Price`, pushed prices are only a fallback
+    pub price_oracle: Option<String>,
+    /// Lock durations offered by `StakeLocked`, none by default
+    pub lock_tiers: Option<Vec<LockTier>>,
+    /// Penalty for leaving a lock early, locks are binding without one
+    pub early_exit_penalty: Option<EarlyExitPenaltyMsg>,
+    /// Keeper bounty on auto-compounded rewards in basis points, 0 by default
+    pub compound_bounty_bps: Option<u16>,
+    /// Makes positions transferable as a cw20 receipt, can't be combined with lock tiers
+    pub receipt_token: Option<ReceiptTokenMsg>,
+}
+
+/// Receipt token the contract instantiates as its minter, with the decimals of the stake token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReceiptTokenMsg {
+    /// Code of `och-receipt-token`, which reports transfers back to the minter
+    pub code_id: u64,
+    pub name: String,
+    pub symbol: String,
+}
+
+/// Instantiates `och-receipt-token`, whose minter is the instantiating contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReceiptTokenInstantiateMsg {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyExitPenaltyMsg {
+    /// Basis points of the early withdrawn principal kept as penalty, at most 10000
+    pub bps: u16,
+    /// Address receiving the penalty instead of the remaining stakers
+    pub treasury: Option<String>,
 }
 
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardAssetMsg {
+    /// Asset rewards are paid out in, e.g. `Cw20("<usdc address>")`
+    pub denom: UncheckedDenom,
+    pub schedules: Vec<RewardSchedule>,
+    /// Price of one whole stake token in whole tokens of this reward asset
+    pub oracle: Option<Decimal>,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -20,14 +81,41 @@ pub enum ExecuteMsg {
     Stake {
 
     },
+    /// Entry point for cw20 `Send`, the embedded message is a `ReceiveMsg`
+    Receive(Cw20ReceiveMsg),
+    /// Unstakes and, with a receipt token, burns as many receipts out of the allowance the
+    /// sender gave this contract
     Unstake {
         amount: u128,
+        /// Take what the unlocked stake doesn't cover out of locks, paying the early exit penalty
+        early_exit: Option<bool>,
     },
+    WithdrawUnbonded {
+
+    },
+    /// Pays the sender's rewards to `recipient`, the sender by default
     ClaimReward {
+        recipient: Option<String>,
+    },
+    /// Lets `operator` claim the sender's rewards, paying `recipient` or the sender
+    AuthorizeOperator {
+        operator: String,
+        recipient: Option<String>,
+    },
+    RevokeOperator {
+        operator: String,
+    },
+    /// Operator claims the rewards of `owner` to the recipient the owner picked
+    ClaimRewardFor {
+        owner: String,
+    },
+    /// Tops up native reward assets with the attached funds
+    FundRewards {
 
     },
     UpdateOracle {
-        price: u128,
+        asset: String,
+        price: Decimal,
     },
     UpdateCurSumIndex {
 
@@ -35,9 +123,175 @@ pub enum ExecuteMsg {
     UpdateUserReward {
         account: String,
     },
-    UpdateRewardPerSecond {
-        new_rps: u128,
-    }
+    /// Queues an emission window after the ones already scheduled for `asset`
+    AddRewardSchedule {
+        asset: String,
+        schedule: RewardSchedule,
+    },
+    AddRewardAsset {
+        denom: UncheckedDenom,
+    },
+    /// Admin offers the admin role to `address`, who has to accept it before `expiry`
+    ProposeNewAdmin {
+        address: String,
+        expiry: Option<Expiration>,
+    },
+    AcceptAdmin {
+
+    },
+    CancelAdminProposal {
+
+    },
+    /// Admin offers the updater role to `address`, who has to accept it before `expiry`
+    ProposeNewUpdater {
+        address: String,
+        expiry: Option<Expiration>,
+    },
+    AcceptUpdater {
+
+    },
+    CancelUpdaterProposal {
+
+    },
+    /// Admin or guardian flips the given switches, `None` leaves one unchanged
+    SetPause {
+        stake: Option<bool>,
+        unstake: Option<bool>,
+        claim: Option<bool>,
+        oracle: Option<bool>,
+    },
+    UpdateGuardian {
+        guardian: Option<String>,
+    },
+    /// Admin changes the price feeder and the limits its prices are held to
+    UpdateOracleConfig {
+        price_feeder: Option<String>,
+        max_price_age: Option<u64>,
+        max_price_deviation: Option<Decimal>,
+        /// Removes the deviation limit, can't be combined with `max_price_deviation`
+        clear_max_price_deviation: Option<bool>,
+    },
+    /// Admin points the contract at an oracle contract, or back at pushed prices only
+    SetPriceOracle {
+        price_oracle: Option<String>,
+    },
+    /// Stakes the sent funds locked for `duration` seconds, which has to match a lock tier
+    StakeLocked {
+        duration: u64,
+    },
+    /// Stakes the attached funds into the position of `recipient`, who alone can unstake them
+    StakeFor {
+        recipient: String,
+    },
+    /// Drops the boost of `address`'s expired locks, callable by anyone so an idle
+    /// locker can't keep earning the multiplier past its expiry
+    Unlock {
+        address: String,
+    },
+    /// Admin replaces the lock tiers, existing positions keep their multiplier
+    UpdateLockTiers {
+        lock_tiers: Vec<LockTier>,
+    },
+    /// Admin sets or removes the early exit penalty
+    UpdateEarlyExitPenalty {
+        early_exit_penalty: Option<EarlyExitPenaltyMsg>,
+    },
+    /// Restakes the sender's rewards in the stake token, for pools rewarding their own token
+    Compound {
+
+    },
+    /// Opts the sender in or out of compounding by keepers
+    SetAutoCompound {
+        enabled: bool,
+    },
+    /// Compounds every opted in address of `addresses`, paying the sender the compound bounty
+    CompoundFor {
+        addresses: Vec<String>,
+    },
+    /// Admin changes the keeper bounty on auto-compounded rewards
+    UpdateCompoundBounty {
+        bps: u16,
+    },
+    /// Admin delegates the pooled native stake to `validators`, moving delegations off
+    /// validators that left the set, at most once per epoch. `unbonding_period` and
+    /// `max_entries` are the chain's, in seconds and entries per validator, 7 by default.
+    UpdateDelegation {
+        validators: Vec<String>,
+        unbonding_period: u64,
+        max_entries: Option<u32>,
+    },
+    /// Withdraws the staking rewards of every delegation and pays them to the stakers, along
+    /// with early exit penalties the chain finished unbonding. Also sends the undelegation
+    /// batch once its epoch is over.
+    Harvest {
+
+    },
+    /// Sent by the receipt token when `amount` of receipts moved from `owner` to `recipient`,
+    /// moves the stake behind them along
+    ReceiptTransfer {
+        owner: String,
+        recipient: String,
+        amount: Uint128,
+    },
+    /// Registers a contract notified with `StakeChangedHook` on every stake change, admin only
+    AddHook {
+        addr: String,
+    },
+    RemoveHook {
+        addr: String,
+    },
+}
+
+/// Sent to every registered hook contract when the stake of `addr` changes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeChangedHookMsg {
+    StakeChangedHook {
+        addr: Addr,
+        old_balance: Uint128,
+        new_balance: Uint128,
+    },
+}
+
+/// Query sent to the external price oracle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQueryMsg {
+    /// Price of one whole `base` token in whole `quote` tokens, both given as native denom or cw20 address
+    Price {
+        base: String,
+        quote: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OraclePriceResponse {
+    pub price: Decimal,
+    pub updated_at: Timestamp,
+}
+
+/// Hook messages carried in `Cw20ReceiveMsg.msg`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Stake {
+
+    },
+    StakeLocked {
+        duration: u64,
+    },
+    /// Stakes the tokens sent into the position of `recipient`
+    StakeFor {
+        recipient: String,
+    },
+    /// Tops up the reward asset of the sending cw20 contract
+    FundRewards {
+
+    },
+    /// Unstakes as much as the receipts sent, which are burnt
+    Unstake {
+
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -47,11 +301,65 @@ pub enum QueryMsg {
         account: String,
     },
     ViewAPR {
-
+        /// Also report the APR this extra stake would get once deposited
+        deposit: Option<Uint128>,
+        /// Price pushed prices at their average over this many seconds instead of the last one
+        twap_window: Option<u64>,
     },
     CheckStakeAmount {
         address: String,
-    }
+    },
+    PendingUnbondings {
+        address: String,
+    },
+    RewardSchedules {
+        asset: String,
+    },
+    RewardReserve {
+
+    },
+    Config {
+
+    },
+    PauseState {
+
+    },
+    /// Walks every staker in address order
+    AllStakers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Time-weighted average of the pushed prices of `asset` over the last `window_seconds`
+    Twap {
+        asset: String,
+        window_seconds: u64,
+    },
+    UserLocks {
+        address: String,
+    },
+    /// Operators authorized to claim for `owner`, in address order
+    Operators {
+        owner: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Stake of `address` at the start of `height`, the current block when `None`
+    VotingPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+    },
+    /// Total stake at the start of `height`, the current block when `None`
+    TotalPowerAtHeight {
+        height: Option<u64>,
+    },
+    /// Cw2 name and version of the contract, as voting modules report them
+    Info {
+
+    },
+    /// Contracts notified of stake changes
+    Hooks {
+
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -66,14 +374,149 @@ pub struct ViewBalance {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ViewRewardResponse {
-    pub reward: Uint128,
+    pub rewards: Vec<RewardAmount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardAmount {
+    pub denom: Denom,
+    pub amount: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ViewAPRResponse {
-    pub apr: Uint128,
+    /// Yearly rewards over the staked value, summed over the priced reward assets.
+    /// Applies to unlocked stake, locks earn it times their multiplier. `None` while nothing is staked.
+    pub apr: Option<Decimal>,
+    /// `apr` compounded daily
+    pub apy: Option<Decimal>,
+    /// APR once the queried `deposit` is added to the stake
+    pub deposit_apr: Option<Decimal>,
+    /// Reward assets left out of the APR because their price is older than `max_price_age`
+    pub stale_prices: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-#[serde(rename_all = "snake_case")]
-pub enum MigrateMsg {}
\ No newline at end of file
+pub struct OperatorsResponse {
+    pub operators: Vec<OperatorResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperatorResponse {
+    pub operator: Addr,
+    pub recipient: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HooksResponse {
+    pub hooks: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VotingPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TotalPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InfoResponse {
+    pub info: ContractVersion,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UserLocksResponse {
+    pub locks: Vec<LockPosition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapResponse {
+    pub price: Decimal,
+    pub window_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingUnbondingsResponse {
+    pub unbondings: Vec<UnbondingInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllStakersResponse {
+    pub stakers: Vec<StakerResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakerResponse {
+    pub address: Addr,
+    pub balance: Uint128,
+    pub auto_compound: bool,
+    pub rewards: Vec<StakerReward>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakerReward {
+    pub denom: Denom,
+    /// Claimable right now, same as `ViewReward`
+    pub pending: Uint128,
+    pub index: Decimal256,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardSchedulesResponse {
+    pub schedules: Vec<RewardSchedule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardReserveResponse {
+    pub reserves: Vec<RewardReserve>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardReserve {
+    pub denom: Denom,
+    pub total_funded: Uint128,
+    pub total_distributed: Uint128,
+    pub total_claimed: Uint128,
+    /// Funded but not yet promised to stakers
+    pub reserve: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub admin: Addr,
+    pub updater: Addr,
+    pub pending_admin: Option<PendingRole>,
+    pub pending_updater: Option<PendingRole>,
+    pub stake_denom: Denom,
+    pub unbonding_period: u64,
+    pub guardian: Option<Addr>,
+    pub price_feeder: Addr,
+    pub max_price_age: u64,
+    pub max_price_deviation: Option<Decimal>,
+    pub price_oracle: Option<Addr>,
+    pub lock_tiers: Vec<LockTier>,
+    pub early_exit_penalty: Option<EarlyExitPenalty>,
+    pub compound_bounty_bps: u16,
+    pub delegation: Option<DelegationConfig>,
+    pub receipt_token: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseStateResponse {
+    pub pause: PauseInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// Replaces the configured unbonding period
+    pub unbonding_period: Option<u64>,
+    /// Stake asset when migrating from 0.1.x, which hardcoded ORAI, defaults to `Native("orai")`
+    pub stake_denom: Option<UncheckedDenom>,
+    /// Reward token 0.1.x paid out, required when migrating from that version
+    pub legacy_reward_denom: Option<UncheckedDenom>,
+}
\ No newline at end of file
@@ -0,0 +1,122 @@
+use cosmwasm_std::{Decimal, StdResult, Storage, Timestamp};
+use math::Uint256;
+
+use crate::error::ContractError;
+use crate::state::{PriceInfo, PriceObservation, ORACLE, PRICE_OBSERVATIONS};
+
+//Pushed prices and the time-weighted average built from them.
+//`record_observation` and `twap` are pure, `save_price` and `load_twap` wrap them around storage.
+
+/// Observations kept per asset, older ones are dropped first
+pub const MAX_PRICE_OBSERVATIONS: usize = 64;
+
+/// Stores `price` for `asset` as of `now` and records it in the TWAP observations
+pub fn save_price(storage: &mut dyn Storage, asset: &str, price: Decimal, now: Timestamp) -> StdResult<()> {
+    ORACLE.save(storage, asset, &PriceInfo { price, updated_at: now })?;
+
+    let mut observations = PRICE_OBSERVATIONS.may_load(storage, asset)?.unwrap_or_default();
+    record_observation(&mut observations, price, now);
+    PRICE_OBSERVATIONS.save(storage, asset, &observations)
+}
+
+/// Average pushed price of `asset` over the `window_seconds` before `now`
+pub fn load_twap(storage: &dyn Storage, asset: &str, now: Timestamp, window_seconds: u64) -> Result<Decimal, ContractError> {
+    let observations = PRICE_OBSERVATIONS.may_load(storage, asset)?.unwrap_or_default();
+    twap(&observations, now, window_seconds)
+}
+
+/// Sum of price atomics times seconds from the first observation up to `at`,
+/// extending `observation` whose price holds from its timestamp on
+fn cumulative_at(observation: &PriceObservation, at: Timestamp) -> Uint256 {
+    let elapsed = at.seconds().saturating_sub(observation.timestamp.seconds());
+    observation.cumulative_price + Uint256::from(observation.price.atomics()) * Uint256::from(elapsed)
+}
+
+/// Appends `price` observed at `now`, dropping the oldest observation once full.
+/// A second price in the same block replaces the first one.
+pub fn record_observation(observations: &mut Vec<PriceObservation>, price: Decimal, now: Timestamp) {
+    if let Some(last) = observations.last_mut() {
+        if last.timestamp.seconds() >= now.seconds() {
+            last.price = price;
+            return;
+        }
+    }
+    let cumulative_price = observations.last().map(|last| cumulative_at(last, now)).unwrap_or_default();
+
+    if observations.len() >= MAX_PRICE_OBSERVATIONS {
+        observations.remove(0);
+    }
+    observations.push(PriceObservation { timestamp: now, cumulative_price, price });
+}
+
+/// Time-weighted average price over the `window_seconds` before `now`. The window
+/// has to start at or after the oldest observation kept.
+pub fn twap(observations: &[PriceObservation], now: Timestamp, window_seconds: u64) -> Result<Decimal, ContractError> {
+    let not_covered = || ContractError::TwapWindowNotCovered{ window_seconds };
+
+    let start = now.seconds().checked_sub(window_seconds).ok_or_else(not_covered)?;
+    let (first, last) = match (observations.first(), observations.last()) {
+        (Some(first), Some(last)) if window_seconds > 0 && first.timestamp.seconds() <= start => (first, last),
+        _ => return Err(not_covered()),
+    };
+
+    //Latest observation at or before the window start
+    let before_start = observations
+        .iter()
+        .rev()
+        .find(|observation| observation.timestamp.seconds() <= start)
+        .unwrap_or(first);
+    let start = Timestamp::from_seconds(start);
+
+    let sum = cumulative_at(last, now) - cumulative_at(before_start, start);
+    let average = sum.checked_multiply_ratio(1u64, window_seconds)?.try_into_uint128()?;
+    Ok(Decimal::new(average))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: u64) -> Timestamp {
+        Timestamp::from_seconds(seconds)
+    }
+
+    #[test]
+    fn twap_weights_prices_by_time() {
+        let mut observations = vec![];
+        record_observation(&mut observations, Decimal::percent(100), at(1000));
+        record_observation(&mut observations, Decimal::percent(300), at(1100));
+
+        // 100s at 1.0 then 100s at 3.0
+        assert_eq!(twap(&observations, at(1200), 200).unwrap(), Decimal::percent(200));
+        // The window may start between observations
+        assert_eq!(twap(&observations, at(1200), 150).unwrap(), Decimal::from_ratio(350u128, 150u128));
+        // Or after the last one
+        assert_eq!(twap(&observations, at(1200), 50).unwrap(), Decimal::percent(300));
+
+        // But not before the oldest one, and never empty
+        assert!(matches!(twap(&observations, at(1200), 201), Err(ContractError::TwapWindowNotCovered { .. })));
+        assert!(matches!(twap(&observations, at(1200), 0), Err(ContractError::TwapWindowNotCovered { .. })));
+        assert!(matches!(twap(&[], at(1200), 10), Err(ContractError::TwapWindowNotCovered { .. })));
+    }
+
+    #[test]
+    fn observations_are_bounded() {
+        let mut observations = vec![];
+        record_observation(&mut observations, Decimal::one(), at(1000));
+        // A second write in the same block replaces the price
+        record_observation(&mut observations, Decimal::percent(200), at(1000));
+        assert_eq!(observations.len(), 1);
+        assert_eq!(observations[0].price, Decimal::percent(200));
+
+        for i in 1..=MAX_PRICE_OBSERVATIONS as u64 {
+            record_observation(&mut observations, Decimal::percent(200), at(1000 + i * 10));
+        }
+        assert_eq!(observations.len(), MAX_PRICE_OBSERVATIONS);
+        assert_eq!(observations[0].timestamp, at(1010));
+
+        // The cumulative price survives dropping the oldest observations
+        let now = at(1000 + MAX_PRICE_OBSERVATIONS as u64 * 10);
+        assert_eq!(twap(&observations, now, 100).unwrap(), Decimal::percent(200));
+    }
+}
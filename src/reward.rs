@@ -0,0 +1,311 @@
+use cosmwasm_std::{Decimal, OverflowError, Order, StdResult, Storage, Timestamp, Uint128};
+use math::{Decimal256, Uint256};
+
+use crate::error::ContractError;
+use crate::state::{asset_key, RewardInfo, RewardSchedule, StakeInfo, UserRewardInfo, REWARDS, TOTAL_WEIGHT};
+
+//Reward accounting shared by every handler and query.
+//`accrue_reward` and `settle_user_rewards` are pure, the rest only wraps them around storage.
+
+const YEAR: u128 = 31536000;
+const DAYS_PER_YEAR: u32 = 365;
+
+/// Loads every reward stream in key order
+pub fn load_rewards(storage: &dyn Storage) -> StdResult<Vec<RewardInfo>> {
+    REWARDS
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, reward)| reward))
+        .collect()
+}
+
+/// Amount `schedule` releases between `from` and `to`, clipped to its own window
+fn emitted_between(schedule: &RewardSchedule, from: Timestamp, to: Timestamp) -> Uint128 {
+    let start = from.max(schedule.start).seconds();
+    let end = to.min(schedule.end).seconds();
+
+    if end <= start {
+        return Uint128::zero();
+    }
+
+    let duration = schedule.end.seconds() - schedule.start.seconds();
+    schedule.total_amount.multiply_ratio(end - start, duration)
+}
+
+/// Current emission rate of `reward_info`, zero between schedules
+pub fn current_rps(reward_info: &RewardInfo, cur: Timestamp) -> Uint128 {
+    reward_info.schedules
+        .iter()
+        .filter(|schedule| schedule.start <= cur && cur < schedule.end)
+        .map(|schedule| schedule.total_amount / Uint128::from(schedule.end.seconds() - schedule.start.seconds()))
+        .sum()
+}
+
+/// Every reward stream as `update_rewards` would leave it at `cur`, without saving
+pub fn accrued_rewards(storage: &dyn Storage, cur: Timestamp) -> Result<Vec<RewardInfo>, ContractError> {
+    let total_weight = TOTAL_WEIGHT.load(storage)?;
+    let mut rewards = load_rewards(storage)?;
+
+    for reward_info in rewards.iter_mut() {
+        accrue_reward(reward_info, total_weight, cur)?;
+    }
+
+    Ok(rewards)
+}
+
+/// Bumps `cur_sum_index` by what the schedules emitted since `last_update`
+/// and drops the schedules that have fully ended. Emission is capped by the
+/// funds not yet distributed, so stakers are never promised unfunded rewards.
+pub fn accrue_reward(reward_info: &mut RewardInfo, total_weight: Uint128, cur: Timestamp) -> Result<(), ContractError> {
+    if cur <= reward_info.last_update {
+        return Ok(());
+    }
+
+    if total_weight > Uint128::zero() {
+        let emitted: Uint128 = reward_info.schedules
+            .iter()
+            .map(|schedule| emitted_between(schedule, reward_info.last_update, cur))
+            .sum();
+        let reward = emitted.min(reward_info.total_funded.checked_sub(reward_info.total_distributed)?);
+        distribute(reward_info, reward, total_weight)?;
+    }
+
+    reward_info.schedules.retain(|schedule| schedule.end > cur);
+    reward_info.last_update = cur;
+    Ok(())
+}
+
+/// Credits `amount` of already funded rewards to `total_weight` through `cur_sum_index`
+pub fn distribute(reward_info: &mut RewardInfo, amount: Uint128, total_weight: Uint128) -> Result<(), ContractError> {
+    let reward_per_token = Decimal256::checked_from_ratio(Uint256::from(amount), Uint256::from(total_weight))?;
+    reward_info.cur_sum_index = reward_info.cur_sum_index.checked_add(reward_per_token)?;
+    reward_info.total_distributed = reward_info.total_distributed.checked_add(amount)?;
+    Ok(())
+}
+
+/// Brings every reward stream up to `cur` and returns them in key order
+pub fn update_rewards(storage: &mut dyn Storage, cur: Timestamp) -> Result<Vec<RewardInfo>, ContractError> {
+    let total_weight = TOTAL_WEIGHT.load(storage)?;
+    let mut rewards = load_rewards(storage)?;
+
+    for reward_info in rewards.iter_mut() {
+        accrue_reward(reward_info, total_weight, cur)?;
+        REWARDS.save(storage, &asset_key(&reward_info.denom), reward_info)?;
+    }
+
+    Ok(rewards)
+}
+
+/// Moves what `stake_info.weight()` earned since its last settlement into pending rewards.
+/// Afterwards `stake_info.rewards` lines up one to one with `rewards`.
+pub fn settle_user_rewards(stake_info: &mut StakeInfo, rewards: &[RewardInfo]) -> Result<(), ContractError> {
+    let mut settled = Vec::with_capacity(rewards.len());
+
+    for reward_info in rewards {
+        let asset = asset_key(&reward_info.denom);
+        //Assets added after the user's last settlement started from a zero index
+        let mut user_reward = stake_info.rewards
+            .iter()
+            .find(|user_reward| user_reward.asset == asset)
+            .cloned()
+            .unwrap_or(UserRewardInfo {
+                asset,
+                reward: Uint128::zero(),
+                index: Decimal256::zero(),
+            });
+
+        let index_delta = reward_info.cur_sum_index.checked_sub(user_reward.index)?;
+        let earned = Uint256::from(stake_info.weight())
+            .checked_mul_decimal(index_delta)?
+            .try_into_uint128()?;
+        user_reward.reward = user_reward.reward.checked_add(earned)?;
+        user_reward.index = reward_info.cur_sum_index;
+        settled.push(user_reward);
+    }
+
+    stake_info.rewards = settled;
+    Ok(())
+}
+
+/// Yearly emission at `rps` over the value of `total_stake`, with amounts in base units
+/// and `price` quoting one whole stake token in whole reward tokens
+pub fn apr(
+    rps: Uint128,
+    total_stake: Uint128,
+    price: Decimal,
+    stake_decimals: u8,
+    reward_decimals: u8,
+) -> Result<Decimal, ContractError> {
+    //Both sides scaled to base units of both tokens, so nothing rounds before the division
+    let yearly_reward = Uint256::from(rps)
+        .checked_mul(Uint256::from(YEAR))?
+        .checked_mul(pow10(stake_decimals)?)?;
+    let staked_amount = Uint256::from(total_stake).checked_mul(pow10(reward_decimals)?)?;
+
+    //Divide by the stake and the price one at a time, each step only scales by the 18 decimals
+    //of the result. Scaling by both at once overflows with 18 decimal tokens on both sides.
+    let reward_per_stake = yearly_reward.checked_multiply_ratio(pow10(18)?, staked_amount)?;
+    let atomics = reward_per_stake.checked_multiply_ratio(pow10(18)?, Uint256::from(price.atomics()))?;
+    Ok(Decimal::new(atomics.try_into_uint128()?))
+}
+
+fn pow10(exponent: u8) -> Result<Uint256, OverflowError> {
+    (0..exponent).try_fold(Uint256::one(), |acc, _| acc.checked_mul(Uint256::from(10u64)))
+}
+
+/// `apr` compounded daily, saturating instead of failing on absurd rates
+pub fn apy(apr: Decimal) -> Decimal {
+    let daily = apr / Uint128::from(DAYS_PER_YEAR);
+    Decimal::one().saturating_add(daily).saturating_pow(DAYS_PER_YEAR) - Decimal::one()
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::mock_dependencies;
+    use cw20::Denom;
+
+    use super::*;
+
+    const START: u64 = 1_000;
+
+    fn reward_info(denom: &str, total_amount: u128, total_funded: u128) -> RewardInfo {
+        RewardInfo {
+            denom: Denom::Native(denom.to_string()),
+            last_update: Timestamp::from_seconds(START),
+            cur_sum_index: Decimal256::zero(),
+            schedules: vec![RewardSchedule {
+                start: Timestamp::from_seconds(START),
+                end: Timestamp::from_seconds(START + 100),
+                total_amount: Uint128::new(total_amount),
+            }],
+            total_funded: Uint128::new(total_funded),
+            total_distributed: Uint128::zero(),
+            total_claimed: Uint128::zero(),
+        }
+    }
+
+    #[test]
+    fn accrual_follows_schedules_and_funding() {
+        // 10 per second, fully funded
+        let mut reward = reward_info("uusdc", 1000, 1000);
+        accrue_reward(&mut reward, Uint128::new(4), Timestamp::from_seconds(START + 30)).unwrap();
+        assert_eq!(reward.cur_sum_index, Decimal256::from_ratio(300u64, 4u64));
+        assert_eq!(reward.total_distributed, Uint128::new(300));
+
+        // Going back in time is a no-op
+        accrue_reward(&mut reward, Uint128::new(4), Timestamp::from_seconds(START)).unwrap();
+        assert_eq!(reward.last_update, Timestamp::from_seconds(START + 30));
+
+        // Nothing is distributed without stake, but the clock still moves
+        accrue_reward(&mut reward, Uint128::zero(), Timestamp::from_seconds(START + 50)).unwrap();
+        assert_eq!(reward.total_distributed, Uint128::new(300));
+        assert_eq!(reward.last_update, Timestamp::from_seconds(START + 50));
+
+        // Ended schedules are dropped
+        accrue_reward(&mut reward, Uint128::new(4), Timestamp::from_seconds(START + 200)).unwrap();
+        assert_eq!(reward.total_distributed, Uint128::new(800));
+        assert!(reward.schedules.is_empty());
+
+        // Emission stops at the funded amount
+        let mut reward = reward_info("uusdc", 1000, 250);
+        accrue_reward(&mut reward, Uint128::new(4), Timestamp::from_seconds(START + 100)).unwrap();
+        assert_eq!(reward.total_distributed, Uint128::new(250));
+        assert_eq!(reward.cur_sum_index, Decimal256::from_ratio(250u64, 4u64));
+    }
+
+    #[test]
+    fn settlement_lines_up_with_reward_streams() {
+        let mut usdc = reward_info("uusdc", 1000, 1000);
+        let mut atom = reward_info("uatom", 300, 300);
+        accrue_reward(&mut usdc, Uint128::new(3), Timestamp::from_seconds(START + 10)).unwrap();
+
+        // A staker settled on usdc only, holding a third of the stake
+        let mut stake_info = StakeInfo {
+            balance: Uint128::new(1),
+            locks: vec![],
+            auto_compound: false,
+            rewards: vec![UserRewardInfo {
+                asset: "uusdc".to_string(),
+                reward: Uint128::new(7),
+                index: usdc.cur_sum_index,
+            }],
+        };
+
+        accrue_reward(&mut usdc, Uint128::new(3), Timestamp::from_seconds(START + 40)).unwrap();
+        accrue_reward(&mut atom, Uint128::new(3), Timestamp::from_seconds(START + 40)).unwrap();
+        let rewards = vec![atom.clone(), usdc.clone()];
+        settle_user_rewards(&mut stake_info, &rewards).unwrap();
+
+        // The new asset is settled from a zero index and comes first, like in `rewards`
+        assert_eq!(stake_info.rewards[0].asset, "uatom");
+        assert_eq!(stake_info.rewards[0].reward, Uint128::new(40));
+        assert_eq!(stake_info.rewards[0].index, atom.cur_sum_index);
+        assert_eq!(stake_info.rewards[1].asset, "uusdc");
+        assert_eq!(stake_info.rewards[1].reward, Uint128::new(107));
+
+        // Settling again at the same indexes changes nothing
+        let settled = stake_info.clone();
+        settle_user_rewards(&mut stake_info, &rewards).unwrap();
+        assert_eq!(stake_info, settled);
+    }
+
+    #[test]
+    fn apr_accounts_for_decimals() {
+        // 1 whole reward token per second over 1000 whole stake tokens worth 2 reward tokens each
+        let apr_6_6 = apr(Uint128::new(1_000_000), Uint128::new(1_000_000_000), Decimal::percent(200), 6, 6).unwrap();
+        assert_eq!(apr_6_6, Decimal::from_ratio(YEAR, 2000u128));
+
+        let apr_18_6 = apr(
+            Uint128::new(1_000_000_000_000_000_000),
+            Uint128::new(1_000_000_000),
+            Decimal::percent(200),
+            6,
+            18,
+        )
+        .unwrap();
+        assert_eq!(apr_18_6, apr_6_6);
+
+        let apr_6_18 = apr(
+            Uint128::new(1_000_000),
+            Uint128::new(1_000_000_000_000_000_000_000),
+            Decimal::percent(200),
+            18,
+            6,
+        )
+        .unwrap();
+        assert_eq!(apr_6_18, apr_6_6);
+
+        let apr_18_18 = apr(
+            Uint128::new(1_000_000_000_000_000_000),
+            Uint128::new(1_000_000_000_000_000_000_000),
+            Decimal::percent(200),
+            18,
+            18,
+        )
+        .unwrap();
+        assert_eq!(apr_18_18, apr_6_6);
+
+        // Daily compounding of 100% lands a bit below e - 1
+        let apy = apy(Decimal::one());
+        assert!(apy > Decimal::percent(171) && apy < Decimal::percent(172));
+    }
+
+    #[test]
+    fn stored_accrual_matches_preview() {
+        let mut deps = mock_dependencies();
+        let reward = reward_info("uusdc", 1000, 1000);
+        REWARDS.save(deps.as_mut().storage, "uusdc", &reward).unwrap();
+        TOTAL_WEIGHT.save(deps.as_mut().storage, &Uint128::new(7)).unwrap();
+
+        let cur = Timestamp::from_seconds(START + 33);
+        let preview = accrued_rewards(deps.as_ref().storage, cur).unwrap();
+        let updated = update_rewards(deps.as_mut().storage, cur).unwrap();
+        assert_eq!(preview, updated);
+        assert_eq!(load_rewards(deps.as_ref().storage).unwrap(), updated);
+
+        // A staker holding all of the stake gets everything distributed, minus rounding
+        let mut stake_info = StakeInfo { balance: Uint128::new(7), ..StakeInfo::default() };
+        settle_user_rewards(&mut stake_info, &updated).unwrap();
+        assert_eq!(stake_info.rewards[0].reward, Uint128::new(329));
+        assert_eq!(updated[0].total_distributed, Uint128::new(330));
+    }
+}
@@ -2,34 +2,261 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Uint128, Timestamp};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Decimal, Uint128, Timestamp};
+use cw_storage_plus::{Item, Map, SnapshotItem, SnapshotMap, Strategy};
+use cw20::{Denom, Expiration};
+use math::{Decimal256, Uint256};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     pub admin: Addr,
     pub updater: Addr,
+    pub stake_denom: Denom,
+    /// Seconds unstaked funds wait in the unbonding queue before they can be withdrawn
+    pub unbonding_period: u64,
+    /// Optional second address allowed to flip the pause switches
+    pub guardian: Option<Addr>,
+    /// Only address allowed to push oracle prices
+    pub price_feeder: Addr,
+    /// Seconds after which a price is stale and left out of the APR
+    pub max_price_age: u64,
+    /// Largest relative move one price update may make, unbounded when `None`
+    pub max_price_deviation: Option<Decimal>,
+    /// External oracle contract asked for prices first, `ORACLE` is the fallback
+    pub price_oracle: Option<Addr>,
+    /// Lock durations `StakeLocked` accepts, with the reward weight each one earns
+    pub lock_tiers: Vec<LockTier>,
+    /// Lets locked stake leave before expiry at a cost, locks are binding when `None`
+    pub early_exit_penalty: Option<EarlyExitPenalty>,
+    /// Share of the rewards compounded by `CompoundFor` paid to the caller, in basis points
+    pub compound_bounty_bps: u16,
+    /// Validators the pooled stake is delegated to, kept in the contract when `None`
+    pub delegation: Option<DelegationConfig>,
+    /// Cw20 minted 1:1 for stake, instantiated and minted by this contract. Positions follow
+    /// the receipts, which report every transfer back through `ReceiptTransfer`.
+    pub receipt_token: Option<Addr>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DelegationConfig {
+    /// Operator addresses sharing new delegations evenly
+    pub validators: Vec<String>,
+    /// Chain unbonding time in seconds, unstaking never releases funds sooner. Undelegated
+    /// coins land at the end of the first block past it, so it must not be shorter than the chain's.
+    pub unbonding_period: u64,
+    /// Chain limit of unbonding and redelegation entries per validator, 7 on most chains
+    pub max_entries: u32,
+}
+
+impl DelegationConfig {
+    /// Seconds between two undelegations or redelegations, never more than `max_entries` of
+    /// them are in flight within one unbonding period
+    pub fn epoch(&self) -> u64 {
+        self.unbonding_period / u64::from(self.max_entries) + 1
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyExitPenalty {
+    /// Share of the principal taken out of a lock early that is kept, in basis points
+    pub bps: u16,
+    /// Receives the penalty, when `None` it is paid to the remaining stakers and waived if there
+    /// are none. Under delegation the penalty unbonds with the rest and is sent over by the first
+    /// harvest after its release.
+    pub treasury: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockTier {
+    /// Seconds the stake stays locked
+    pub duration: u64,
+    /// Reward weight of each locked token, at least 1
+    pub multiplier: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct StakeInfo {
+    /// Everything staked, locked or not
     pub balance: Uint128,
+    /// Positions locked out of `balance` until they expire
+    pub locks: Vec<LockPosition>,
+    /// One entry per reward asset the user has been settled against
+    pub rewards: Vec<UserRewardInfo>,
+    /// Lets anyone restake the user's stake token rewards through `CompoundFor`
+    pub auto_compound: bool,
+}
+
+impl StakeInfo {
+    /// Part of `balance` that can't be unstaked yet
+    pub fn locked(&self) -> Uint128 {
+        self.locks.iter().map(|lock| lock.amount).sum()
+    }
+
+    /// Stake rewards are distributed by, `balance` plus the boost of every lock.
+    /// An expired lock keeps its boost until the account is settled again, which
+    /// any `Unlock` or `UpdateUserReward` call for it does.
+    pub fn weight(&self) -> Uint128 {
+        self.balance + self.locks.iter().map(|lock| lock.weight - lock.amount).sum::<Uint128>()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockPosition {
+    pub amount: Uint128,
+    pub multiplier: Decimal,
+    /// `amount` boosted by `multiplier`
+    pub weight: Uint128,
+    pub unlock_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UserRewardInfo {
+    /// Key of the reward asset in `REWARDS`
+    pub asset: String,
     pub reward: Uint128,
-    pub index: Uint128,
+    pub index: Decimal256,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct RewardInfo {
+    pub denom: Denom,
     pub last_update: Timestamp,
-    pub cur_sum_index: Uint128,
-    pub rps: Uint128,
-    pub total_stake: Uint128,
+    /// Reward paid per staked token since the stream started, with 18 decimals
+    pub cur_sum_index: Decimal256,
+    /// Funded emission windows, ordered by start and never overlapping
+    pub schedules: Vec<RewardSchedule>,
+    /// Everything ever deposited to pay this reward
+    pub total_funded: Uint128,
+    /// Everything credited to stakers through `cur_sum_index`, never above `total_funded`
+    pub total_distributed: Uint128,
+    /// Everything paid out by claims
+    pub total_claimed: Uint128,
 }
 
-pub const USERS: Map<Addr, StakeInfo> = Map::new("users");
-pub const CONFIG: Item<Config> = Item::new("config");
-pub const REWARD: Item<RewardInfo> = Item::new("reward");
-pub const ORACLE: Item<Uint128> = Item::new("oracle");
+impl RewardInfo {
+    /// Empty stream of `denom` starting from a zero index at `now`
+    pub fn new(denom: Denom, now: Timestamp) -> Self {
+        RewardInfo {
+            denom,
+            last_update: now,
+            cur_sum_index: Decimal256::zero(),
+            schedules: vec![],
+            total_funded: Uint128::zero(),
+            total_distributed: Uint128::zero(),
+            total_claimed: Uint128::zero(),
+        }
+    }
+}
+
+/// Releases `total_amount` linearly between `start` and `end`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardSchedule {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub total_amount: Uint128,
+}
+
+/// Emergency switches, each one halts a single family of operations
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseInfo {
+    pub stake: bool,
+    pub unstake: bool,
+    pub claim: bool,
+    pub oracle: bool,
+}
+
+/// Holder proposed for the admin or updater role, waiting to accept it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingRole {
+    pub address: Addr,
+    pub expiry: Expiration,
+}
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceInfo {
+    pub price: Decimal,
+    pub updated_at: Timestamp,
+}
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceObservation {
+    pub timestamp: Timestamp,
+    /// Sum of price atomics times the seconds each price held, up to `timestamp`
+    pub cumulative_price: Uint256,
+    /// Price written at `timestamp`, holding until the next observation
+    pub price: Decimal,
+}
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingInfo {
+    pub amount: Uint128,
+    pub release_at: Timestamp,
+    /// Undelegation batch the amount leaves the validators with, only released once the chain
+    /// released the batch as well
+    #[serde(default)]
+    pub batch: Option<u64>,
+}
+
+/// Delegated stake unstaked since the last undelegation, sent as one batch once `send_at` passed
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct UndelegationBatch {
+    pub id: u64,
+    pub amount: Uint128,
+    pub send_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OperatorGrant {
+    /// Receives what the operator claims, the owner when `None`
+    pub recipient: Option<Addr>,
+}
+
+pub const USERS: Map<Addr, StakeInfo> = Map::new("users");
+/// Addresses allowed to claim rewards on an owner's behalf, keyed by (owner, operator)
+pub const OPERATORS: Map<(Addr, Addr), OperatorGrant> = Map::new("operators");
+pub const CONFIG: Item<Config> = Item::new("config");
+/// Contracts sent `StakeChangedHook` whenever a stake balance changes
+pub const HOOKS: Item<Vec<Addr>> = Item::new("hooks");
+/// Snapshotted every block a stake moves, the total voting power
+pub const TOTAL_STAKE: SnapshotItem<Uint128> = SnapshotItem::new(
+    "total_stake",
+    "total_stake__checkpoints",
+    "total_stake__changelog",
+    Strategy::EveryBlock,
+);
+/// `StakeInfo::balance` of every staker snapshotted by height, their voting power
+pub const STAKED_BALANCES: SnapshotMap<Addr, Uint128> = SnapshotMap::new(
+    "staked_balances",
+    "staked_balances__checkpoints",
+    "staked_balances__changelog",
+    Strategy::EveryBlock,
+);
+/// Sum of every staker's `StakeInfo::weight`, what rewards are split by
+pub const TOTAL_WEIGHT: Item<Uint128> = Item::new("total_weight");
+/// Reward streams keyed by `asset_key` of their denom
+pub const REWARDS: Map<&str, RewardInfo> = Map::new("rewards");
+/// Price of one whole stake token in whole tokens of each reward asset, keyed like `REWARDS`
+pub const ORACLE: Map<&str, PriceInfo> = Map::new("oracle");
+/// Latest pushed prices per reward asset, oldest first and at most `MAX_PRICE_OBSERVATIONS`
+pub const PRICE_OBSERVATIONS: Map<&str, Vec<PriceObservation>> = Map::new("price_observations");
+pub const UNBONDINGS: Map<Addr, Vec<UnbondingInfo>> = Map::new("unbondings");
+pub const UNDELEGATION_BATCH: Item<UndelegationBatch> = Item::new("undelegation_batch");
+/// Time the chain releases every sent undelegation batch, by batch id
+pub const SENT_BATCHES: Map<u64, Timestamp> = Map::new("sent_batches");
+/// Last time delegations moved between validators, at most once per `DelegationConfig::epoch`
+pub const LAST_REDELEGATION: Item<Timestamp> = Item::new("last_redelegation");
+/// Early exit penalties for the stakers still unbonding on chain, credited once released
+pub const PENDING_PENALTIES: Item<Vec<UnbondingInfo>> = Item::new("pending_penalties");
+/// Early exit penalties for each treasury still unbonding on chain, sent out once released
+pub const TREASURY_PENALTIES: Map<Addr, Vec<UnbondingInfo>> = Map::new("treasury_penalties");
+pub const PENDING_ADMIN: Item<PendingRole> = Item::new("pending_admin");
+pub const PENDING_UPDATER: Item<PendingRole> = Item::new("pending_updater");
+pub const PAUSE: Item<PauseInfo> = Item::new("pause");
+
+/// Storage key and message identifier of a reward asset: the native denom or the cw20 address
+pub fn asset_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(denom) => denom.clone(),
+        Denom::Cw20(addr) => addr.to_string(),
+    }
+}